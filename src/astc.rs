@@ -0,0 +1,594 @@
+use std::array;
+
+// Cpu decoder for ldr astc blocks, following the decoding process of the khronos data format
+// spec. Illegal blocks and hdr endpoints decode to the error color, like they do on hardware
+// that only supports the ldr profile
+
+const ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+// Quantization ranges endpoints can use, weights only go up to 32
+const RANGES: [u32; 21] = [
+    2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32, 40, 48, 64, 80, 96, 128, 160, 192, 256,
+];
+
+fn mask(count: u32) -> u32 {
+    ((1u64 << count) - 1) as u32
+}
+
+// Reads fields of a block from its lowest bit up, bits past the end read as zero
+struct Bits {
+    bits: u128,
+    end: u32,
+}
+
+impl Bits {
+    fn get(&self, offset: u32, count: u32) -> u32 {
+        if count == 0 || offset >= self.end {
+            return 0;
+        }
+        (self.bits >> offset) as u32 & mask(count.min(self.end - offset))
+    }
+}
+
+// Splits a range into its bits and whether the values also use a trit (3) or quint (5)
+fn encoding(levels: u32) -> (u32, u32) {
+    match levels {
+        _ if levels.is_power_of_two() => (levels.trailing_zeros(), 1),
+        _ if levels % 3 == 0 => ((levels / 3).trailing_zeros(), 3),
+        _ => ((levels / 5).trailing_zeros(), 5),
+    }
+}
+
+// Size of an integer sequence, trits pack five values into 8 bits and quints three into 7
+fn sequence_bits(count: u32, levels: u32) -> u32 {
+    let (bits, kind) = encoding(levels);
+    count * bits
+        + match kind {
+            3 => (8 * count).div_ceil(5),
+            5 => (7 * count).div_ceil(3),
+            _ => 0,
+        }
+}
+
+fn decode_trits(t: u32) -> [u32; 5] {
+    let bit = |value: u32, i: u32| (value >> i) & 1;
+
+    let (c, t3, t4) = match (t >> 2) & 7 {
+        7 => ((t >> 5) << 2 | (t & 3), 2, 2),
+        _ if (t >> 5) & 3 == 3 => (t & 31, bit(t, 7), 2),
+        _ => (t & 31, (t >> 5) & 3, bit(t, 7)),
+    };
+    let (t0, t1, t2) = match c {
+        _ if c & 3 == 3 => (bit(c, 3) << 1 | (bit(c, 2) & !bit(c, 3) & 1), bit(c, 4), 2),
+        _ if (c >> 2) & 3 == 3 => (c & 3, 2, 2),
+        _ => (
+            bit(c, 1) << 1 | (bit(c, 0) & !bit(c, 1) & 1),
+            (c >> 2) & 3,
+            bit(c, 4),
+        ),
+    };
+
+    [t0, t1, t2, t3, t4]
+}
+
+fn decode_quints(q: u32) -> [u32; 3] {
+    let bit = |value: u32, i: u32| (value >> i) & 1;
+
+    if (q >> 1) & 3 == 3 && (q >> 5) & 3 == 0 {
+        let not_q0 = !q & 1;
+        return [
+            4,
+            4,
+            bit(q, 0) << 2 | (bit(q, 4) & not_q0) << 1 | (bit(q, 3) & not_q0),
+        ];
+    }
+
+    let (c, q2) = match (q >> 1) & 3 {
+        3 => (((q >> 3) & 3) << 3 | (!(q >> 5) & 3) << 1 | (q & 1), 4),
+        _ => (q & 31, (q >> 5) & 3),
+    };
+    match c & 7 {
+        5 => [(c >> 3) & 3, 4, q2],
+        _ => [c & 7, (c >> 3) & 3, q2],
+    }
+}
+
+// Reads count values of an integer sequence, the trits and quints of a group are spread
+// between the bits of its values
+fn decode_sequence(bits: &Bits, offset: u32, levels: u32, values: &mut [u32]) {
+    let (bit_count, kind) = encoding(levels);
+    let mut offset = offset;
+    let mut read = |count: u32| {
+        let value = bits.get(offset, count);
+        offset += count;
+        value
+    };
+
+    match kind {
+        3 => {
+            for group in values.chunks_mut(5) {
+                let mut low = [0; 5];
+                let mut t = 0;
+                for (i, (low, t_bits)) in low.iter_mut().zip([2, 2, 1, 2, 1]).enumerate() {
+                    *low = read(bit_count);
+                    t |= read(t_bits) << [0, 2, 4, 5, 7][i];
+                }
+                for ((value, trit), low) in group.iter_mut().zip(decode_trits(t)).zip(low) {
+                    *value = trit << bit_count | low;
+                }
+            }
+        }
+        5 => {
+            for group in values.chunks_mut(3) {
+                let mut low = [0; 3];
+                let mut q = 0;
+                for (i, (low, q_bits)) in low.iter_mut().zip([3, 2, 2]).enumerate() {
+                    *low = read(bit_count);
+                    q |= read(q_bits) << [0, 3, 5][i];
+                }
+                for ((value, quint), low) in group.iter_mut().zip(decode_quints(q)).zip(low) {
+                    *value = quint << bit_count | low;
+                }
+            }
+        }
+        _ => values.iter_mut().for_each(|value| *value = read(bit_count)),
+    }
+}
+
+// Repeats the bits of a value until it fills the wider field
+fn replicate(value: u32, bits: u32, to: u32) -> u32 {
+    if bits == 0 {
+        return 0;
+    }
+
+    let mut result = 0;
+    let mut filled = 0;
+    while filled < to {
+        result = result << bits | value;
+        filled += bits;
+    }
+    result >> (filled - to)
+}
+
+// Endpoint values scaled to 0..=255
+fn unquantize_color(value: u32, levels: u32) -> u32 {
+    let (bits, kind) = encoding(levels);
+    if kind == 1 {
+        return replicate(value, bits, 8);
+    }
+
+    let (low, d) = (value & mask(bits), value >> bits);
+    let a = (low & 1) * 0x1ff;
+    let bit = |i: u32| (low >> i) & 1;
+    let high = low >> 1;
+
+    let (b, c) = match (kind, bits) {
+        (3, 1) => (0, 204),
+        (5, 1) => (0, 113),
+        (3, 2) => (bit(1) * 0b100010110, 93),
+        (5, 2) => (bit(1) * 0b100001100, 54),
+        (3, 3) => (bit(2) * 0b100001010 + bit(1) * 0b010000101, 44),
+        (5, 3) => (bit(2) * 0b100000101 + bit(1) * 0b010000010, 26),
+        (3, 4) => (high << 6 | high, 22),
+        (5, 4) => (high << 6 | high >> 1, 13),
+        (3, 5) => (high << 5 | high >> 2, 11),
+        (5, 5) => (high << 5 | high >> 3, 6),
+        _ => (high << 4 | high >> 4, 5),
+    };
+
+    let t = (d * c + b) ^ a;
+    (a & 0x80) | (t >> 2)
+}
+
+// Weights scaled to 0..=64
+fn unquantize_weight(value: u32, levels: u32) -> u32 {
+    let (bits, kind) = encoding(levels);
+
+    let weight = match (kind, bits) {
+        (1, _) => replicate(value, bits, 6),
+        (3, 0) => [0, 32, 63][value as usize],
+        (5, 0) => [0, 16, 32, 47, 63][value as usize],
+        _ => {
+            let (low, d) = (value & mask(bits), value >> bits);
+            let a = (low & 1) * 0x7f;
+            let bit = |i: u32| (low >> i) & 1;
+
+            let (b, c) = match (kind, bits) {
+                (3, 1) => (0, 50),
+                (5, 1) => (0, 28),
+                (3, 2) => (bit(1) * 0b1000101, 23),
+                (5, 2) => (bit(1) * 0b1000010, 13),
+                _ => (bit(2) * 0b1000010 + bit(1) * 0b0100001, 11),
+            };
+
+            let t = (d * c + b) ^ a;
+            (a & 0x20) | (t >> 2)
+        }
+    };
+
+    weight + (weight > 32) as u32
+}
+
+struct BlockMode {
+    width: u32,
+    height: u32,
+    dual_plane: bool,
+    levels: u32,
+}
+
+// Weight grid size and range of the low 11 bits, None for reserved modes
+fn block_mode(mode: u32) -> Option<BlockMode> {
+    let bit = |i: u32| (mode >> i) & 1;
+    let (a, b) = ((mode >> 5) & 3, (mode >> 7) & 3);
+    let (mut dual_plane, mut high) = (bit(10) == 1, bit(9) == 1);
+
+    let (range, width, height) = match mode & 3 {
+        0 => {
+            let range = bit(4) | (mode >> 2 & 3) << 1;
+            let (width, height) = match b {
+                _ if (mode >> 2) & 3 == 0 => return None,
+                0 => (12, a + 2),
+                1 => (a + 2, 12),
+                2 => {
+                    (dual_plane, high) = (false, false);
+                    (a + 6, ((mode >> 9) & 3) + 6)
+                }
+                _ => match a {
+                    0 => (6, 10),
+                    1 => (10, 6),
+                    _ => return None,
+                },
+            };
+            (range, width, height)
+        }
+        low => {
+            let range = bit(4) | low << 1;
+            let (width, height) = match (mode >> 2) & 3 {
+                0 => (b + 4, a + 2),
+                1 => (b + 8, a + 2),
+                2 => (a + 2, b + 8),
+                _ if bit(8) == 0 => (a + 2, bit(7) + 6),
+                _ => (bit(7) + 2, a + 2),
+            };
+            (range, width, height)
+        }
+    };
+
+    if range < 2 {
+        return None;
+    }
+    let levels = match high {
+        false => [2, 3, 4, 5, 6, 8][range as usize - 2],
+        true => [10, 12, 16, 20, 24, 32][range as usize - 2],
+    };
+
+    Some(BlockMode {
+        width,
+        height,
+        dual_plane,
+        levels,
+    })
+}
+
+// Partition of a texel, from the hash the spec uses to generate its partition patterns
+fn select_partition(seed: u32, x: u32, y: u32, partitions: u32, small_block: bool) -> usize {
+    let (x, y) = match small_block {
+        true => (x << 1, y << 1),
+        false => (x, y),
+    };
+    let seed = seed + (partitions - 1) * 1024;
+
+    let mut rnum = seed;
+    rnum ^= rnum >> 15;
+    rnum = rnum.wrapping_sub(rnum << 17);
+    rnum = rnum.wrapping_add(rnum << 7);
+    rnum = rnum.wrapping_add(rnum << 4);
+    rnum ^= rnum >> 5;
+    rnum = rnum.wrapping_add(rnum << 16);
+    rnum ^= rnum >> 7;
+    rnum ^= rnum >> 3;
+    rnum ^= rnum << 6;
+    rnum ^= rnum >> 17;
+
+    // Only the seeds for x and y, z is for 3d blocks which wgpu has no formats for
+    let mut seeds: [u32; 8] = array::from_fn(|i| ((rnum >> (i * 4)) & 15).pow(2));
+
+    let (sh1, sh2) = match seed & 1 {
+        1 => (4 + (seed & 2 == 0) as u32, 5 + (partitions == 3) as u32),
+        _ => (5 + (partitions == 3) as u32, 4 + (seed & 2 == 0) as u32),
+    };
+    for (i, seed) in seeds.iter_mut().enumerate() {
+        *seed >>= [sh1, sh2][i % 2];
+    }
+
+    let a = (seeds[0] * x + seeds[1] * y + (rnum >> 14)) & 63;
+    let b = (seeds[2] * x + seeds[3] * y + (rnum >> 10)) & 63;
+    let c = match partitions {
+        3.. => (seeds[4] * x + seeds[5] * y + (rnum >> 6)) & 63,
+        _ => 0,
+    };
+    let d = match partitions {
+        4 => (seeds[6] * x + seeds[7] * y + (rnum >> 2)) & 63,
+        _ => 0,
+    };
+
+    match () {
+        _ if a >= b && a >= c && a >= d => 0,
+        _ if b >= c && b >= d => 1,
+        _ if c >= d => 2,
+        _ => 3,
+    }
+}
+
+// Moves the top bit of the offset into the base, leaving a signed 6 bit offset
+fn bit_transfer_signed(offset: &mut i32, base: &mut i32) {
+    *base >>= 1;
+    *base |= *offset & 0x80;
+    *offset >>= 1;
+    *offset &= 0x3f;
+    if *offset & 0x20 != 0 {
+        *offset -= 0x40;
+    }
+}
+
+fn blue_contract(color: [i32; 4]) -> [i32; 4] {
+    [
+        (color[0] + color[2]) >> 1,
+        (color[1] + color[2]) >> 1,
+        color[2],
+        color[3],
+    ]
+}
+
+// The two rgba endpoints of a partition, None for hdr endpoint modes
+fn decode_endpoints(mode: u32, values: &[u32]) -> Option<[[u8; 4]; 2]> {
+    let mut v: [i32; 8] = array::from_fn(|i| values.get(i).copied().unwrap_or(0) as i32);
+
+    let (e0, e1) = match mode {
+        0 => ([v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]),
+        1 => {
+            let l0 = (v[0] >> 2) | (v[1] & 0xc0);
+            let l1 = (l0 + (v[1] & 0x3f)).min(255);
+            ([l0, l0, l0, 255], [l1, l1, l1, 255])
+        }
+        4 => ([v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]),
+        5 => {
+            let [v0, v1, v2, v3, ..] = &mut v;
+            bit_transfer_signed(v1, v0);
+            bit_transfer_signed(v3, v2);
+            let l1 = v[0] + v[1];
+            ([v[0], v[0], v[0], v[2]], [l1, l1, l1, v[2] + v[3]])
+        }
+        6 | 10 => {
+            let scale = |channel: i32| (channel * v[3]) >> 8;
+            let (a0, a1) = match mode {
+                10 => (v[4], v[5]),
+                _ => (255, 255),
+            };
+            (
+                [scale(v[0]), scale(v[1]), scale(v[2]), a0],
+                [v[0], v[1], v[2], a1],
+            )
+        }
+        8 | 12 => {
+            let (a0, a1) = match mode {
+                12 => (v[6], v[7]),
+                _ => (255, 255),
+            };
+            let e0 = [v[0], v[2], v[4], a0];
+            let e1 = [v[1], v[3], v[5], a1];
+            match v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                true => (e0, e1),
+                false => (blue_contract(e1), blue_contract(e0)),
+            }
+        }
+        9 | 13 => {
+            for pair in v.chunks_exact_mut(2) {
+                let [base, offset] = pair else { unreachable!() };
+                bit_transfer_signed(offset, base);
+            }
+            let (a0, a1) = match mode {
+                13 => (v[6], v[6] + v[7]),
+                _ => (255, 255),
+            };
+            let e0 = [v[0], v[2], v[4], a0];
+            let e1 = [v[0] + v[1], v[2] + v[3], v[4] + v[5], a1];
+            match v[1] + v[3] + v[5] >= 0 {
+                true => (e0, e1),
+                false => (blue_contract(e1), blue_contract(e0)),
+            }
+        }
+        _ => return None,
+    };
+
+    Some([e0, e1].map(|endpoint| endpoint.map(|channel| channel.clamp(0, 255) as u8)))
+}
+
+// Decodes a block into rows of rgba8 texels
+pub fn decode_block(block: &[u8], footprint: (u32, u32), srgb: bool, texels: &mut [u8]) {
+    let texels: &mut [[u8; 4]] = bytemuck::cast_slice_mut(texels);
+
+    match decode_colors(block, footprint, srgb, texels) {
+        Some(()) => {}
+        None => texels.fill(ERROR_COLOR),
+    }
+}
+
+fn decode_colors(
+    block: &[u8],
+    (block_width, block_height): (u32, u32),
+    srgb: bool,
+    texels: &mut [[u8; 4]],
+) -> Option<()> {
+    let bits = Bits {
+        bits: u128::from_le_bytes(block[..16].try_into().unwrap()),
+        end: 128,
+    };
+
+    // Void extent blocks have a single 16 bit color, hdr ones are illegal in ldr formats
+    if bits.get(0, 9) == 0x1fc {
+        let extent: [u32; 4] = array::from_fn(|i| bits.get(12 + i as u32 * 13, 13));
+        let all_ones = extent.iter().all(|&coordinate| coordinate == 0x1fff);
+        if bits.get(9, 1) == 1 || (!all_ones && (extent[0] >= extent[1] || extent[2] >= extent[3]))
+        {
+            return None;
+        }
+
+        let color = array::from_fn(|i| (bits.get(64 + i as u32 * 16, 16) >> 8) as u8);
+        texels.fill(color);
+        return Some(());
+    }
+
+    let mode = block_mode(bits.get(0, 11))?;
+    let planes = 1 + mode.dual_plane as u32;
+    let weight_count = mode.width * mode.height * planes;
+    let weight_bits = sequence_bits(weight_count, mode.levels);
+    let partitions = bits.get(11, 2) + 1;
+
+    if mode.width > block_width
+        || mode.height > block_height
+        || weight_count > 64
+        || !(24..=96).contains(&weight_bits)
+        || (partitions == 4 && mode.dual_plane)
+    {
+        return None;
+    }
+
+    // Everything below the weights, which fill the block from the top down
+    let mut below_weights = 128 - weight_bits;
+    let (seed, color_offset, endpoint_modes) = match partitions {
+        1 => (0, 17, [bits.get(13, 4); 4]),
+        _ => {
+            let encoded = bits.get(23, 6);
+            let modes = match encoded & 3 {
+                0 => [encoded >> 2; 4],
+                class => {
+                    // Each partition picks the base class or the next one plus two mode bits,
+                    // the bits that don't fit sit right below the weights
+                    let extra_bits = 3 * partitions - 4;
+                    below_weights -= extra_bits;
+                    let encoded = encoded >> 2 | bits.get(below_weights, extra_bits) << 4;
+
+                    array::from_fn(|i| {
+                        let i = (i as u32).min(partitions - 1);
+                        let class = class - 1 + ((encoded >> i) & 1);
+                        class << 2 | (encoded >> (partitions + i * 2)) & 3
+                    })
+                }
+            };
+            (bits.get(13, 10), 29, modes)
+        }
+    };
+    let dual_plane_channel = match mode.dual_plane {
+        true => {
+            below_weights -= 2;
+            Some(bits.get(below_weights, 2) as usize)
+        }
+        false => None,
+    };
+
+    // Endpoints use the finest range that fits the space left, which has to be at least 6
+    let value_count: u32 = endpoint_modes[..partitions as usize]
+        .iter()
+        .map(|mode| ((mode >> 2) + 1) * 2)
+        .sum();
+    let color_space = below_weights.checked_sub(color_offset)?;
+    let color_levels = RANGES
+        .iter()
+        .copied()
+        .rev()
+        .find(|&levels| sequence_bits(value_count, levels) <= color_space)
+        .filter(|&levels| levels >= 6 && value_count <= 18)?;
+
+    let mut values = [0; 18];
+    let color_bits = Bits {
+        bits: bits.bits,
+        end: color_offset + sequence_bits(value_count, color_levels),
+    };
+    decode_sequence(
+        &color_bits,
+        color_offset,
+        color_levels,
+        &mut values[..value_count as usize],
+    );
+
+    let mut endpoints = [None; 4];
+    let mut values = values.map(|value| unquantize_color(value, color_levels));
+    let mut remaining = &mut values[..];
+    for (endpoints, mode) in endpoints
+        .iter_mut()
+        .zip(endpoint_modes)
+        .take(partitions as usize)
+    {
+        let (partition_values, rest) = remaining.split_at_mut((((mode >> 2) + 1) * 2) as usize);
+        *endpoints = Some(decode_endpoints(mode, partition_values));
+        remaining = rest;
+    }
+
+    // Weights are stored bit reversed from the top of the block
+    let mut weights = [0; 64];
+    let weight_bits = Bits {
+        bits: bits.bits.reverse_bits(),
+        end: weight_bits,
+    };
+    decode_sequence(
+        &weight_bits,
+        0,
+        mode.levels,
+        &mut weights[..weight_count as usize],
+    );
+    let weights = weights.map(|weight| unquantize_weight(weight, mode.levels));
+
+    // The weight grid can be coarser than the block and gets bilinearly upsampled
+    let scale_x = (1024 + block_width / 2) / (block_width - 1);
+    let scale_y = (1024 + block_height / 2) / (block_height - 1);
+    let infill = |x: u32, y: u32, plane: u32| {
+        let grid_x = (scale_x * x * (mode.width - 1) + 32) >> 6;
+        let grid_y = (scale_y * y * (mode.height - 1) + 32) >> 6;
+        let (fx, fy) = (grid_x & 15, grid_y & 15);
+        let (x, y) = (grid_x >> 4, grid_y >> 4);
+
+        let weight = |dx: u32, dy: u32| {
+            let (x, y) = ((x + dx).min(mode.width - 1), (y + dy).min(mode.height - 1));
+            weights[((y * mode.width + x) * planes + plane) as usize]
+        };
+        let w11 = (fx * fy + 8) >> 4;
+        let (w10, w01) = (fy - w11, fx - w11);
+        let w00 = 16 + w11 - fx - fy;
+
+        (weight(0, 0) * w00 + weight(1, 0) * w01 + weight(0, 1) * w10 + weight(1, 1) * w11 + 8) >> 4
+    };
+
+    let small_block = block_width * block_height < 31;
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let (x, y) = (i as u32 % block_width, i as u32 / block_width);
+        let partition = match partitions {
+            1 => 0,
+            _ => select_partition(seed, x, y, partitions, small_block),
+        };
+        let Some([e0, e1]) = endpoints[partition].flatten() else {
+            *texel = ERROR_COLOR;
+            continue;
+        };
+
+        let weight = infill(x, y, 0);
+        let second_weight = dual_plane_channel.map(|_| infill(x, y, 1));
+        *texel = array::from_fn(|channel| {
+            let weight = match dual_plane_channel {
+                Some(dual) if dual == channel => second_weight.unwrap(),
+                _ => weight,
+            };
+
+            // Endpoints expand to 16 bits before interpolating, with the top 8 bits of the
+            // result being the texel
+            let expand = |value: u8| match srgb {
+                true => (value as u32) << 8 | 0x80,
+                false => value as u32 * 257,
+            };
+            let (c0, c1) = (expand(e0[channel]), expand(e1[channel]));
+            ((c0 * (64 - weight) + c1 * weight + 32) >> 6 >> 8) as u8
+        });
+    }
+
+    Some(())
+}
@@ -1,59 +1,243 @@
-use encase::ShaderType;
 use nalgebra_glm as glm;
 
+use crate::display::DisplayRect;
+use crate::uniform::Uniform;
+
+// Laid out like the wgsl struct
+// struct Camera {
+//     view_proj: mat4x4<f32>,
+//     view: mat4x4<f32>,
+//     proj: mat4x4<f32>,
+//     inv_view_proj: mat4x4<f32>,
+//     inv_view: mat4x4<f32>,
+//     inv_proj: mat4x4<f32>,
+//     position: vec3<f32>,
+//     viewport: vec2<f32>,
+// };
+// view_proj comes first so shaders only declaring it keep working
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
     pub view_proj: [[f32; 4]; 4],
+    pub view: [[f32; 4]; 4],
+    pub proj: [[f32; 4]; 4],
+    pub inv_view_proj: [[f32; 4]; 4],
+    pub inv_view: [[f32; 4]; 4],
+    pub inv_proj: [[f32; 4]; 4],
+    pub position: [f32; 3],
+    _padding: f32,
+    pub viewport: [f32; 2],
+    _padding_end: [f32; 2],
 }
 
 impl Default for CameraUniform {
     fn default() -> Self {
+        let identity: [[f32; 4]; 4] = glm::Mat4::identity().into();
+
         Self {
-            view_proj: glm::Mat4::identity().into(),
+            view_proj: identity,
+            view: identity,
+            proj: identity,
+            inv_view_proj: identity,
+            inv_view: identity,
+            inv_proj: identity,
+            position: [0.0; 3],
+            _padding: 0.0,
+            viewport: [0.0; 2],
+            _padding_end: [0.0; 2],
         }
     }
 }
 
-pub struct OrthoCamera {
-    pub pos: glm::Vec3,
-    pub target: glm::Vec3,
-    pub up: glm::Vec3,
-    pub znear: f32,
-    pub zfar: f32,
-    pub zoom: f32,
+impl CameraUniform {
+    pub fn new<C: Camera + ?Sized>(camera: &C, viewport: &glm::UVec2) -> Self {
+        let view = camera.view();
+        let proj = camera.proj(viewport);
+        let view_proj = proj * view;
 
-    pub uniform: CameraUniform,
+        Self {
+            view_proj: view_proj.into(),
+            view: view.into(),
+            proj: proj.into(),
+            inv_view_proj: view_proj
+                .try_inverse()
+                .unwrap_or_else(glm::Mat4::identity)
+                .into(),
+            inv_view: view
+                .try_inverse()
+                .unwrap_or_else(glm::Mat4::identity)
+                .into(),
+            inv_proj: proj
+                .try_inverse()
+                .unwrap_or_else(glm::Mat4::identity)
+                .into(),
+            position: camera.position().into(),
+            _padding: 0.0,
+            viewport: [viewport.x as f32, viewport.y as f32],
+            _padding_end: [0.0; 2],
+        }
+    }
 }
 
-impl OrthoCamera {
-    pub fn update(&mut self, new_window_size: &glm::UVec2) {
-        self.uniform.view_proj = self.build_view_proj_matrix(new_window_size).into();
+// Where on the screen the camera's image ends up, in pixels with y pointing down. Usually
+// the whole window, or the DisplayRect of the texture the camera rendered to
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
     }
 
-    pub fn build_view_matrix(&self) -> glm::Mat4 {
-        glm::look_at_rh(&self.pos, &self.target, &self.up)
+    pub fn from_size(size: &glm::UVec2) -> Self {
+        Self::new(0.0, 0.0, size.x as f32, size.y as f32)
     }
 
-    pub fn build_proj_matrix(&self, new_window_size: &glm::UVec2) -> glm::Mat4 {
-        let width = new_window_size.x as f32;
-        let height = new_window_size.y as f32;
+    fn screen_to_ndc(&self, point: &glm::Vec2) -> glm::Vec2 {
+        glm::vec2(
+            (point.x - self.x) / self.width * 2.0 - 1.0,
+            1.0 - (point.y - self.y) / self.height * 2.0,
+        )
+    }
 
-        let scale_x = width / self.zoom;
-        let scale_y = height / self.zoom;
+    fn ndc_to_screen(&self, ndc: &glm::Vec2) -> glm::Vec2 {
+        glm::vec2(
+            self.x + (ndc.x + 1.0) / 2.0 * self.width,
+            self.y + (1.0 - ndc.y) / 2.0 * self.height,
+        )
+    }
+}
 
-        let proj = glm::ortho(-scale_x, scale_x, -scale_y, scale_y, -self.zfar, self.zfar);
+impl From<DisplayRect> for Viewport {
+    fn from(rect: DisplayRect) -> Self {
+        Self::new(rect.x, rect.y, rect.width, rect.height)
+    }
+}
 
-        proj
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: glm::Vec3,
+    // Normalized
+    pub direction: glm::Vec3,
+}
+
+impl Ray {
+    pub fn point_at(&self, distance: f32) -> glm::Vec3 {
+        self.origin + self.direction * distance
     }
+}
+
+// Object safe so render code can take a &dyn Camera. The screen conversions use the
+// matrices of the last update
+pub trait Camera {
+    fn position(&self) -> glm::Vec3;
 
-    pub fn build_view_proj_matrix(&self, window_size: &glm::UVec2) -> glm::Mat4 {
-        let view = self.build_view_matrix();
-        let proj = self.build_proj_matrix(window_size);
+    fn view(&self) -> glm::Mat4;
 
-        proj * view
+    fn proj(&self, viewport: &glm::UVec2) -> glm::Mat4;
+
+    fn view_proj(&self, viewport: &glm::UVec2) -> glm::Mat4 {
+        self.proj(viewport) * self.view()
     }
 
+    // Matrices as of the last update
+    fn camera_uniform(&self) -> &CameraUniform;
+
+    // Recomputes the uniform, has to be called after moving the camera or resizing
+    fn update(&mut self, viewport: &glm::UVec2);
+
+    // Depth is what ends up in the depth buffer, 0 on the near plane and 1 on the far one
+    // for cameras with wgpu's depth range
+    fn screen_to_world(&self, point: &glm::Vec2, depth: f32, viewport: &Viewport) -> glm::Vec3 {
+        let inv_view_proj = glm::Mat4::from(self.camera_uniform().inv_view_proj);
+        let ndc = viewport.screen_to_ndc(point);
+
+        let world = inv_view_proj * glm::vec4(ndc.x, ndc.y, depth, 1.0);
+        world.xyz() / world.w
+    }
+
+    // Ray through the point from the front of the depth range, for picking
+    fn screen_ray(&self, point: &glm::Vec2, viewport: &Viewport) -> Ray {
+        let near = self.screen_to_world(point, 0.0, viewport);
+        let far = self.screen_to_world(point, 1.0, viewport);
+
+        Ray {
+            origin: near,
+            direction: (far - near).normalize(),
+        }
+    }
+
+    // None for points behind the camera
+    fn world_to_screen(&self, point: &glm::Vec3, viewport: &Viewport) -> Option<glm::Vec2> {
+        let view_proj = glm::Mat4::from(self.camera_uniform().view_proj);
+
+        let clip = view_proj * glm::vec4(point.x, point.y, point.z, 1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+
+        Some(viewport.ndc_to_screen(&(clip.xy() / clip.w)))
+    }
+
+    fn uniform(&self) -> Uniform {
+        Uniform {
+            data: bytemuck::bytes_of(self.camera_uniform()).to_vec(),
+            stages: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            dynamic: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OrthoOrigin {
+    // Camera position at the top left corner with y pointing down, like screen coordinates
+    TopLeft,
+    #[default]
+    Center,
+}
+
+// What the visible area of an OrthoCamera is based on, zoom magnifies all of them
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OrthoProjection {
+    // One world unit per pixel at zoom 1
+    Pixels { origin: OrthoOrigin },
+    // Always this many world units vertically, the width follows the aspect ratio
+    FixedHeight(f32),
+    // Always this many world units horizontally, the height follows the aspect ratio
+    FixedWidth(f32),
+}
+
+impl Default for OrthoProjection {
+    fn default() -> Self {
+        OrthoProjection::Pixels {
+            origin: OrthoOrigin::default(),
+        }
+    }
+}
+
+pub struct OrthoCamera {
+    pub pos: glm::Vec3,
+    pub target: glm::Vec3,
+    pub up: glm::Vec3,
+    pub znear: f32,
+    pub zfar: f32,
+    pub zoom: f32,
+    pub projection: OrthoProjection,
+
+    pub uniform: CameraUniform,
+}
+
+impl OrthoCamera {
     pub fn new(
         pos: glm::Vec3,
         target: glm::Vec3,
@@ -72,46 +256,114 @@ impl OrthoCamera {
             znear,
             uniform,
             zoom,
+            projection: OrthoProjection::default(),
         }
     }
-}
 
-pub struct PerspectiveCamera {
-    pub pos: glm::Vec3,
-    pub target: glm::Vec3,
-    pub up: glm::Vec3,
-    pub near: f32,
-    pub far: f32,
-    pub fov: f32,
+    pub fn with_projection(mut self, projection: OrthoProjection) -> Self {
+        self.projection = projection;
+        self
+    }
 
-    pub uniform: CameraUniform,
+    // Left, right, bottom and top of the visible area relative to the camera position
+    pub fn bounds(&self, viewport: &glm::UVec2) -> (f32, f32, f32, f32) {
+        let width = viewport.x.max(1) as f32;
+        let height = viewport.y.max(1) as f32;
+
+        match self.projection {
+            OrthoProjection::Pixels {
+                origin: OrthoOrigin::TopLeft,
+            } => (0.0, width / self.zoom, height / self.zoom, 0.0),
+            // Whole pixel offsets keep texels on the pixel grid for odd viewport sizes
+            OrthoProjection::Pixels {
+                origin: OrthoOrigin::Center,
+            } => {
+                let left = -(width / 2.0).floor() / self.zoom;
+                let bottom = -(height / 2.0).floor() / self.zoom;
+                (
+                    left,
+                    left + width / self.zoom,
+                    bottom,
+                    bottom + height / self.zoom,
+                )
+            }
+            OrthoProjection::FixedHeight(units) => {
+                let half_height = units / self.zoom / 2.0;
+                let half_width = half_height * width / height;
+                (-half_width, half_width, -half_height, half_height)
+            }
+            OrthoProjection::FixedWidth(units) => {
+                let half_width = units / self.zoom / 2.0;
+                let half_height = half_width * height / width;
+                (-half_width, half_width, -half_height, half_height)
+            }
+        }
+    }
+
+    // World units covered by one pixel of the viewport
+    pub fn pixel_size(&self, viewport: &glm::UVec2) -> f32 {
+        let (left, right, _, _) = self.bounds(viewport);
+        (right - left) / viewport.x.max(1) as f32
+    }
+
+    // Position rounded to the pixel grid on the x and y axes, for cameras looking down z
+    pub fn snapped_position(&self, viewport: &glm::UVec2) -> glm::Vec3 {
+        let pixel = self.pixel_size(viewport);
+
+        glm::vec3(
+            (self.pos.x / pixel).round() * pixel,
+            (self.pos.y / pixel).round() * pixel,
+            self.pos.z,
+        )
+    }
+
+    // Moves the camera (and its target along with it) onto the pixel grid so pixel art
+    // doesn't shimmer while the camera moves
+    pub fn snap_to_pixel_grid(&mut self, viewport: &glm::UVec2) {
+        let offset = self.snapped_position(viewport) - self.pos;
+
+        self.pos += offset;
+        self.target += offset;
+    }
 }
 
-impl PerspectiveCamera {
-    pub fn update(&mut self, window_size: &glm::UVec2) {
-        self.uniform.view_proj = self.build_view_proj_matrix(window_size).into();
+impl Camera for OrthoCamera {
+    fn position(&self) -> glm::Vec3 {
+        self.pos
     }
 
-    pub fn build_view_matrix(&self) -> glm::Mat4 {
+    fn view(&self) -> glm::Mat4 {
         glm::look_at_rh(&self.pos, &self.target, &self.up)
     }
 
-    pub fn build_proj_matrix(&self, window_size: &glm::UVec2) -> glm::Mat4 {
-        let width = window_size.x as f32;
-        let height = window_size.y as f32;
-
-        let aspect_ratio = width / height;
+    // Depth maps znear..zfar to wgpu's 0..1 range
+    fn proj(&self, viewport: &glm::UVec2) -> glm::Mat4 {
+        let (left, right, bottom, top) = self.bounds(viewport);
 
-        glm::perspective(aspect_ratio, self.fov, self.near, self.far)
+        glm::ortho_rh_zo(left, right, bottom, top, self.znear, self.zfar)
     }
 
-    pub fn build_view_proj_matrix(&self, window_size: &glm::UVec2) -> glm::Mat4 {
-        let view = self.build_view_matrix();
-        let proj = self.build_proj_matrix(window_size);
+    fn camera_uniform(&self) -> &CameraUniform {
+        &self.uniform
+    }
 
-        proj * view
+    fn update(&mut self, viewport: &glm::UVec2) {
+        self.uniform = CameraUniform::new(self, viewport);
     }
+}
 
+pub struct PerspectiveCamera {
+    pub pos: glm::Vec3,
+    pub target: glm::Vec3,
+    pub up: glm::Vec3,
+    pub near: f32,
+    pub far: f32,
+    pub fov: f32,
+
+    pub uniform: CameraUniform,
+}
+
+impl PerspectiveCamera {
     pub fn new(
         pos: glm::Vec3,
         target: glm::Vec3,
@@ -133,3 +385,30 @@ impl PerspectiveCamera {
         }
     }
 }
+
+impl Camera for PerspectiveCamera {
+    fn position(&self) -> glm::Vec3 {
+        self.pos
+    }
+
+    fn view(&self) -> glm::Mat4 {
+        glm::look_at_rh(&self.pos, &self.target, &self.up)
+    }
+
+    fn proj(&self, viewport: &glm::UVec2) -> glm::Mat4 {
+        let width = viewport.x.max(1) as f32;
+        let height = viewport.y.max(1) as f32;
+
+        let aspect_ratio = width / height;
+
+        glm::perspective_rh_zo(aspect_ratio, self.fov, self.near, self.far)
+    }
+
+    fn camera_uniform(&self) -> &CameraUniform {
+        &self.uniform
+    }
+
+    fn update(&mut self, viewport: &glm::UVec2) {
+        self.uniform = CameraUniform::new(self, viewport);
+    }
+}
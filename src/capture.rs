@@ -0,0 +1,396 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+use crate::readback::TextureReadback;
+use crate::renderer::{RenderError, RenderingContext, TextureHandle};
+use crate::tonemap::{TonemapOperator, Tonemapping};
+
+#[derive(Debug)]
+pub enum CaptureError {
+    Image(image::ImageError),
+    Render(RenderError),
+}
+
+impl std::fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureError::Image(err) => write!(f, "{err}"),
+            CaptureError::Render(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+impl From<image::ImageError> for CaptureError {
+    fn from(err: image::ImageError) -> Self {
+        CaptureError::Image(err)
+    }
+}
+
+impl From<std::io::Error> for CaptureError {
+    fn from(err: std::io::Error) -> Self {
+        CaptureError::Image(image::ImageError::IoError(err))
+    }
+}
+
+impl From<RenderError> for CaptureError {
+    fn from(err: RenderError) -> Self {
+        CaptureError::Render(err)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let encoded = match value <= 0.0031308 {
+        true => value * 12.92,
+        false => 1.055 * value.powf(1.0 / 2.4) - 0.055,
+    };
+
+    (encoded * 255.0).round() as u8
+}
+
+fn capturable(format: wgpu::TextureFormat) -> bool {
+    matches!(
+        format,
+        wgpu::TextureFormat::Rgba8UnormSrgb
+            | wgpu::TextureFormat::Bgra8UnormSrgb
+            | wgpu::TextureFormat::Rgba8Unorm
+            | wgpu::TextureFormat::Bgra8Unorm
+            | wgpu::TextureFormat::Rgba16Float
+            | wgpu::TextureFormat::Rgba32Float
+    )
+}
+
+// Column major like the wgsl matrices in display.wgsl
+fn mat3_mul(matrix: [f32; 9], color: [f32; 3]) -> [f32; 3] {
+    std::array::from_fn(|row| {
+        (0..3)
+            .map(|column| matrix[column * 3 + row] * color[column])
+            .sum()
+    })
+}
+
+// Cpu version of the agx curve in display.wgsl, with the same constants
+#[allow(clippy::excessive_precision)]
+fn agx(color: [f32; 3]) -> [f32; 3] {
+    #[rustfmt::skip]
+    const INSET: [f32; 9] = [
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104,
+    ];
+    #[rustfmt::skip]
+    const OUTSET: [f32; 9] = [
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116,
+    ];
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    let contrast = |x: f32| {
+        let (x2, x4) = (x * x, x * x * x * x);
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    };
+
+    let x = mat3_mul(INSET, color).map(|x| {
+        let x = x.max(1e-10).log2().clamp(MIN_EV, MAX_EV);
+        contrast((x - MIN_EV) / (MAX_EV - MIN_EV))
+    });
+    mat3_mul(OUTSET, x).map(|x| x.max(0.0).powf(2.2))
+}
+
+// Exposure and operator the way display_tex applies them for an sdr surface
+fn tonemap(tonemapping: &Tonemapping, pixel: [f32; 4]) -> [f32; 4] {
+    let color = [pixel[0], pixel[1], pixel[2]].map(|channel| channel * tonemapping.exposure);
+    let positive = color.map(|channel| channel.max(0.0));
+
+    let [r, g, b] = match tonemapping.operator {
+        TonemapOperator::None => color,
+        TonemapOperator::Reinhard => positive.map(|channel| channel / (1.0 + channel)),
+        TonemapOperator::Aces => positive
+            .map(|x| ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)),
+        TonemapOperator::AgX => agx(positive),
+    };
+
+    [r, g, b, pixel[3]]
+}
+
+// Srgb encoded rgba8 the way the texture looks on an sdr surface. Unorm formats are taken as
+// already encoded, hdr ones get tonemapped and alpha is kept as is
+fn to_srgb_rgba8(
+    format: wgpu::TextureFormat,
+    tonemapping: &Tonemapping,
+    mut data: Vec<u8>,
+) -> Vec<u8> {
+    let encode = |pixel: [f32; 4]| {
+        let pixel = tonemap(tonemapping, pixel);
+        [
+            linear_to_srgb(pixel[0]),
+            linear_to_srgb(pixel[1]),
+            linear_to_srgb(pixel[2]),
+            (pixel[3].clamp(0.0, 1.0) * 255.0).round() as u8,
+        ]
+    };
+
+    match format {
+        wgpu::TextureFormat::Rgba8UnormSrgb | wgpu::TextureFormat::Rgba8Unorm => data,
+        wgpu::TextureFormat::Bgra8UnormSrgb | wgpu::TextureFormat::Bgra8Unorm => {
+            data.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+            data
+        }
+        // The readback data has no alignment guarantees, so no casting to float slices
+        wgpu::TextureFormat::Rgba16Float => data
+            .chunks_exact(8)
+            .flat_map(|pixel| {
+                encode(std::array::from_fn(|idx| {
+                    half::f16::from_le_bytes([pixel[idx * 2], pixel[idx * 2 + 1]]).to_f32()
+                }))
+            })
+            .collect(),
+        wgpu::TextureFormat::Rgba32Float => data
+            .chunks_exact(16)
+            .flat_map(|pixel| {
+                encode(std::array::from_fn(|idx| {
+                    f32::from_le_bytes(pixel[idx * 4..idx * 4 + 4].try_into().unwrap())
+                }))
+            })
+            .collect(),
+        _ => unreachable!("checked by capturable"),
+    }
+}
+
+struct EncodeJob {
+    path: PathBuf,
+    format: wgpu::TextureFormat,
+    tonemapping: Tonemapping,
+    dimensions: (u32, u32),
+    data: Vec<u8>,
+}
+
+impl EncodeJob {
+    fn save(self) -> Result<(), CaptureError> {
+        image::save_buffer_with_format(
+            &self.path,
+            &to_srgb_rgba8(self.format, &self.tonemapping, self.data),
+            self.dimensions.0,
+            self.dimensions.1,
+            image::ExtendedColorType::Rgba8,
+            image::ImageFormat::Png,
+        )?;
+
+        Ok(())
+    }
+}
+
+struct PendingFrame {
+    path: PathBuf,
+    format: wgpu::TextureFormat,
+    // Settings at the time the frame was displayed
+    tonemapping: Tonemapping,
+    dimensions: (u32, u32),
+    readback: TextureReadback,
+    map_requested: bool,
+}
+
+// Reads back every frame without waiting on the gpu and encodes the pngs on its own thread
+pub(crate) struct Recorder {
+    directory: PathBuf,
+    frame: u32,
+    pending: Vec<PendingFrame>,
+    sender: mpsc::Sender<EncodeJob>,
+    encoder: JoinHandle<Result<(), CaptureError>>,
+    // First error on the render thread, encoding errors come back from the encoder thread
+    error: Option<CaptureError>,
+}
+
+impl Recorder {
+    fn new(directory: PathBuf) -> Self {
+        let (sender, receiver) = mpsc::channel::<EncodeJob>();
+
+        // Keeps encoding after an error so every frame that can be saved is
+        let encoder = std::thread::spawn(move || {
+            let mut result = Ok(());
+            for job in receiver {
+                let saved = job.save();
+                if result.is_ok() {
+                    result = saved;
+                }
+            }
+
+            result
+        });
+
+        Self {
+            directory,
+            frame: 0,
+            pending: Vec::new(),
+            sender,
+            encoder,
+            error: None,
+        }
+    }
+
+    // Frames whose readback finished go to the encoder thread
+    fn send_mapped(&mut self) {
+        // Frames whose map failed are dropped, their buffers would never be freed otherwise
+        let (failed, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition::<Vec<_>, _>(|frame| frame.readback.is_failed());
+        if !failed.is_empty() {
+            self.error.get_or_insert(RenderError::ReadbackFailed.into());
+        }
+
+        let (mapped, pending) = pending
+            .into_iter()
+            .partition::<Vec<_>, _>(|frame| frame.readback.is_mapped());
+        self.pending = pending;
+
+        for frame in mapped {
+            let _ = self.sender.send(EncodeJob {
+                path: frame.path,
+                format: frame.format,
+                tonemapping: frame.tonemapping,
+                dimensions: frame.dimensions,
+                data: frame.readback.data(),
+            });
+        }
+    }
+}
+
+impl<'a> RenderingContext<'a> {
+    // Blocks until the texture is read back and written to path as png
+    pub fn capture_frame(
+        &mut self,
+        texture: TextureHandle,
+        path: impl AsRef<Path>,
+    ) -> Result<(), CaptureError> {
+        let info = self
+            .textures
+            .get(texture)
+            .ok_or(RenderError::StaleTexture(texture))?;
+        let (format, dimensions) = (info.format, info.dimensions);
+
+        if !capturable(format) {
+            return Err(RenderError::UnsupportedFormat(format).into());
+        }
+
+        EncodeJob {
+            path: path.as_ref().to_owned(),
+            format,
+            tonemapping: self.tonemapping,
+            dimensions,
+            data: self.read_texture(texture)?,
+        }
+        .save()
+    }
+
+    // Every texture display_tex shows on the main surface gets written to directory as
+    // frame_00000.png, frame_00001.png... until stop_recording
+    pub fn start_recording(&mut self, directory: impl Into<PathBuf>) -> Result<(), CaptureError> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+
+        // A recording in progress is dropped, frames it already read back still get written
+        self.recorder = Some(Recorder::new(directory));
+
+        Ok(())
+    }
+
+    // Waits for the outstanding frames and returns how many were recorded
+    pub fn stop_recording(&mut self) -> Result<u32, CaptureError> {
+        let Some(mut recorder) = self.recorder.take() else {
+            return Ok(0);
+        };
+
+        for frame in recorder.pending.iter_mut() {
+            if !frame.map_requested {
+                frame.readback.map();
+            }
+        }
+        self.device.poll(wgpu::Maintain::Wait);
+        recorder.send_mapped();
+
+        if !recorder.pending.is_empty() {
+            recorder
+                .error
+                .get_or_insert(RenderError::ReadbackFailed.into());
+        }
+
+        drop(recorder.sender);
+        let encoded = recorder
+            .encoder
+            .join()
+            .expect("Capture encoder thread panicked");
+
+        match recorder.error {
+            Some(err) => Err(err),
+            None => encoded.map(|_| recorder.frame),
+        }
+    }
+
+    pub fn recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    // Queues the copy of a displayed texture, display_tex submits the encoder afterwards
+    pub(crate) fn record_frame(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: TextureHandle,
+    ) {
+        let Some(recorder) = self.recorder.as_mut() else {
+            return;
+        };
+        let Some(texture) = self.textures.get(texture) else {
+            return;
+        };
+
+        if !texture.usage.contains(wgpu::TextureUsages::COPY_SRC) {
+            let err = RenderError::MissingUsage(wgpu::TextureUsages::COPY_SRC);
+            recorder.error.get_or_insert(err.into());
+            return;
+        }
+        if !capturable(texture.format) {
+            let err = RenderError::UnsupportedFormat(texture.format);
+            recorder.error.get_or_insert(err.into());
+            return;
+        }
+
+        let Some(readback) = TextureReadback::new(&self.device, encoder, texture) else {
+            return;
+        };
+
+        recorder.pending.push(PendingFrame {
+            path: recorder
+                .directory
+                .join(format!("frame_{:05}.png", recorder.frame)),
+            format: texture.format,
+            tonemapping: self.tonemapping,
+            dimensions: texture.dimensions,
+            readback,
+            map_requested: false,
+        });
+        recorder.frame += 1;
+    }
+
+    // Maps the copies submitted since the last call and hands finished ones to the encoder
+    pub(crate) fn poll_recording(&mut self) {
+        let Some(recorder) = self.recorder.as_mut() else {
+            return;
+        };
+
+        for frame in recorder.pending.iter_mut() {
+            if !frame.map_requested {
+                frame.readback.map();
+                frame.map_requested = true;
+            }
+        }
+
+        self.device.poll(wgpu::Maintain::Poll);
+        recorder.send_mapped();
+    }
+}
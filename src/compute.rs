@@ -0,0 +1,127 @@
+use crate::renderer::TextureHandle;
+use crate::shader::ComputeShader;
+use crate::texture::TextureLayoutKey;
+use crate::uniform::Uniform;
+
+pub type BufferHandle = usize;
+
+pub struct StorageBuffer {
+    pub buffer: wgpu::Buffer,
+    pub size: u64,
+    // Compute shaders get the buffer as read_write, every stage can bind it as read only
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    pub read_only_bind_group_layout: wgpu::BindGroupLayout,
+    pub read_only_bind_group: wgpu::BindGroup,
+}
+
+impl StorageBuffer {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, data: &[u8], size: u64) -> Self {
+        // Copies (and therefore readback) need sizes aligned to 4 bytes
+        let size = wgpu::util::align_to(size.max(data.len() as u64), wgpu::COPY_BUFFER_ALIGNMENT);
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("storage buffer"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        if !data.is_empty() {
+            let mut data = data.to_vec();
+            data.resize(wgpu::util::align_to(data.len(), 4), 0);
+            queue.write_buffer(&buffer, 0, &data);
+        }
+
+        let (bind_group_layout, bind_group) =
+            Self::create_binding(device, &buffer, wgpu::ShaderStages::COMPUTE, false);
+
+        let (read_only_bind_group_layout, read_only_bind_group) = Self::create_binding(
+            device,
+            &buffer,
+            wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE,
+            true,
+        );
+
+        crate::debug!("Created new storage buffer {}B", size);
+
+        Self {
+            buffer,
+            size,
+            bind_group_layout,
+            bind_group,
+            read_only_bind_group_layout,
+            read_only_bind_group,
+        }
+    }
+
+    fn create_binding(
+        device: &wgpu::Device,
+        buffer: &wgpu::Buffer,
+        visibility: wgpu::ShaderStages,
+        read_only: bool,
+    ) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        (layout, bind_group)
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, offset: u64, data: &[u8]) {
+        queue.write_buffer(&self.buffer, offset, data);
+    }
+}
+
+// Every binding ends up in its own bind group, in the order they were given
+#[derive(Clone)]
+pub enum ComputeBinding<'a> {
+    Uniform(&'a Uniform),
+    Buffer {
+        buffer: BufferHandle,
+        read_only: bool,
+    },
+    // Sampled texture, bound the same way as in render passes (sampler, texture)
+    Texture(TextureHandle),
+    StorageTexture {
+        texture: TextureHandle,
+        access: wgpu::StorageTextureAccess,
+    },
+}
+
+// What a binding contributes to the pipeline layout
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ComputeBindingKey {
+    Uniform(usize),
+    Buffer { read_only: bool },
+    Texture(TextureLayoutKey),
+    StorageTexture(TextureLayoutKey),
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ComputePipelineInfo {
+    pub(crate) shader: ComputeShader,
+    pub(crate) bindings: Vec<ComputeBindingKey>,
+}
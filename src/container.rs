@@ -0,0 +1,426 @@
+use std::path::Path;
+use std::time::Instant;
+
+use crate::decompress::{decompress, decompressed_format};
+use crate::renderer::{RenderError, RenderingContext, TextureHandle};
+use crate::texture::{data_size, full_mip_count, Texture, TextureDesc, TextureKind};
+
+#[derive(Debug)]
+pub enum ContainerError {
+    Io(std::io::Error),
+    Ktx2(ktx2::ParseError),
+    Dds(ddsfile::Error),
+    // Formats without a wgpu equivalent and supercompressed ktx2 files
+    Unsupported(String),
+    Render(RenderError),
+}
+
+impl std::fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContainerError::Io(err) => write!(f, "{err}"),
+            ContainerError::Ktx2(err) => write!(f, "Invalid ktx2 file: {err}"),
+            ContainerError::Dds(err) => write!(f, "Invalid dds file: {err}"),
+            ContainerError::Unsupported(what) => write!(f, "Unsupported {what}"),
+            ContainerError::Render(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ContainerError {}
+
+impl From<std::io::Error> for ContainerError {
+    fn from(err: std::io::Error) -> Self {
+        ContainerError::Io(err)
+    }
+}
+
+impl From<ktx2::ParseError> for ContainerError {
+    fn from(err: ktx2::ParseError) -> Self {
+        ContainerError::Ktx2(err)
+    }
+}
+
+impl From<ddsfile::Error> for ContainerError {
+    fn from(err: ddsfile::Error) -> Self {
+        ContainerError::Dds(err)
+    }
+}
+
+impl From<RenderError> for ContainerError {
+    fn from(err: RenderError) -> Self {
+        ContainerError::Render(err)
+    }
+}
+
+// Contents of either container in the layout uploads use
+struct ContainerTexture {
+    format: wgpu::TextureFormat,
+    dimensions: (u32, u32),
+    kind: TextureKind,
+    // Every layer (or depth slice) of a level back to back, largest level first
+    levels: Vec<Vec<u8>>,
+}
+
+// Size and amount of layers of a mip level, only 3d textures shrink in depth
+fn level_extent(dimensions: (u32, u32), kind: TextureKind, level: u32) -> ((u32, u32), u32) {
+    let size = (
+        (dimensions.0 >> level).max(1),
+        (dimensions.1 >> level).max(1),
+    );
+
+    match kind {
+        TextureKind::D3 { depth } => (size, (depth >> level).max(1)),
+        kind => (size, kind.layers()),
+    }
+}
+
+fn ktx2_format(format: ktx2::Format) -> Option<wgpu::TextureFormat> {
+    use ktx2::Format as K;
+    use wgpu::TextureFormat as F;
+
+    // Astc formats come in unorm and srgb pairs for every block size
+    if (K::ASTC_4x4_UNORM_BLOCK.value()..=K::ASTC_12x12_SRGB_BLOCK.value())
+        .contains(&format.value())
+    {
+        use wgpu::AstcBlock as B;
+
+        let index = format.value() - K::ASTC_4x4_UNORM_BLOCK.value();
+        let block = [
+            B::B4x4,
+            B::B5x4,
+            B::B5x5,
+            B::B6x5,
+            B::B6x6,
+            B::B8x5,
+            B::B8x6,
+            B::B8x8,
+            B::B10x5,
+            B::B10x6,
+            B::B10x8,
+            B::B10x10,
+            B::B12x10,
+            B::B12x12,
+        ][index as usize / 2];
+        let channel = match index % 2 {
+            0 => wgpu::AstcChannel::Unorm,
+            _ => wgpu::AstcChannel::UnormSrgb,
+        };
+
+        return Some(F::Astc { block, channel });
+    }
+
+    Some(match format {
+        K::R8_UNORM => F::R8Unorm,
+        K::R8G8_UNORM => F::Rg8Unorm,
+        K::R8G8B8A8_UNORM => F::Rgba8Unorm,
+        K::R8G8B8A8_SRGB => F::Rgba8UnormSrgb,
+        K::B8G8R8A8_UNORM => F::Bgra8Unorm,
+        K::B8G8R8A8_SRGB => F::Bgra8UnormSrgb,
+        K::R16G16B16A16_SFLOAT => F::Rgba16Float,
+        K::R32G32B32A32_SFLOAT => F::Rgba32Float,
+        K::BC1_RGB_UNORM_BLOCK | K::BC1_RGBA_UNORM_BLOCK => F::Bc1RgbaUnorm,
+        K::BC1_RGB_SRGB_BLOCK | K::BC1_RGBA_SRGB_BLOCK => F::Bc1RgbaUnormSrgb,
+        K::BC2_UNORM_BLOCK => F::Bc2RgbaUnorm,
+        K::BC2_SRGB_BLOCK => F::Bc2RgbaUnormSrgb,
+        K::BC3_UNORM_BLOCK => F::Bc3RgbaUnorm,
+        K::BC3_SRGB_BLOCK => F::Bc3RgbaUnormSrgb,
+        K::BC4_UNORM_BLOCK => F::Bc4RUnorm,
+        K::BC4_SNORM_BLOCK => F::Bc4RSnorm,
+        K::BC5_UNORM_BLOCK => F::Bc5RgUnorm,
+        K::BC5_SNORM_BLOCK => F::Bc5RgSnorm,
+        K::BC6H_UFLOAT_BLOCK => F::Bc6hRgbUfloat,
+        K::BC6H_SFLOAT_BLOCK => F::Bc6hRgbFloat,
+        K::BC7_UNORM_BLOCK => F::Bc7RgbaUnorm,
+        K::BC7_SRGB_BLOCK => F::Bc7RgbaUnormSrgb,
+        K::ETC2_R8G8B8_UNORM_BLOCK => F::Etc2Rgb8Unorm,
+        K::ETC2_R8G8B8_SRGB_BLOCK => F::Etc2Rgb8UnormSrgb,
+        K::ETC2_R8G8B8A1_UNORM_BLOCK => F::Etc2Rgb8A1Unorm,
+        K::ETC2_R8G8B8A1_SRGB_BLOCK => F::Etc2Rgb8A1UnormSrgb,
+        K::ETC2_R8G8B8A8_UNORM_BLOCK => F::Etc2Rgba8Unorm,
+        K::ETC2_R8G8B8A8_SRGB_BLOCK => F::Etc2Rgba8UnormSrgb,
+        K::EAC_R11_UNORM_BLOCK => F::EacR11Unorm,
+        K::EAC_R11_SNORM_BLOCK => F::EacR11Snorm,
+        K::EAC_R11G11_UNORM_BLOCK => F::EacRg11Unorm,
+        K::EAC_R11G11_SNORM_BLOCK => F::EacRg11Snorm,
+        _ => return None,
+    })
+}
+
+fn read_ktx2(bytes: &[u8]) -> Result<ContainerTexture, ContainerError> {
+    let reader = ktx2::Reader::new(bytes)?;
+    let header = reader.header();
+
+    if let Some(scheme) = header.supercompression_scheme {
+        return Err(ContainerError::Unsupported(format!(
+            "ktx2 supercompression {scheme:?}"
+        )));
+    }
+
+    let format = header
+        .format
+        .and_then(ktx2_format)
+        .ok_or_else(|| ContainerError::Unsupported(format!("ktx2 format {:?}", header.format)))?;
+
+    // Counts are zero rather than one for textures that aren't arrays or 3d
+    let kind = match (header.pixel_depth, header.face_count, header.layer_count) {
+        (0, 6, 0) => TextureKind::Cube,
+        (0, 6, cubes) => TextureKind::CubeArray { cubes },
+        (0, _, 0) => TextureKind::D2,
+        (0, _, layers) => TextureKind::D2Array { layers },
+        (depth, _, _) => TextureKind::D3 { depth },
+    };
+
+    Ok(ContainerTexture {
+        format,
+        dimensions: (header.pixel_width, header.pixel_height.max(1)),
+        kind,
+        levels: reader.levels().map(|level| level.data.to_vec()).collect(),
+    })
+}
+
+fn dds_format(dds: &ddsfile::Dds) -> Option<wgpu::TextureFormat> {
+    use ddsfile::DxgiFormat as D;
+    use wgpu::TextureFormat as F;
+
+    // Old style files without the dx10 header mostly resolve to a dxgi format too
+    let Some(format) = dds.get_dxgi_format() else {
+        return match dds.get_d3d_format()? {
+            ddsfile::D3DFormat::A8B8G8R8 => Some(F::Rgba8Unorm),
+            ddsfile::D3DFormat::A8R8G8B8 => Some(F::Bgra8Unorm),
+            ddsfile::D3DFormat::A16B16G16R16F => Some(F::Rgba16Float),
+            ddsfile::D3DFormat::A32B32G32R32F => Some(F::Rgba32Float),
+            _ => None,
+        };
+    };
+
+    Some(match format {
+        D::R8_UNorm => F::R8Unorm,
+        D::R8G8_UNorm => F::Rg8Unorm,
+        D::R8G8B8A8_UNorm => F::Rgba8Unorm,
+        D::R8G8B8A8_UNorm_sRGB => F::Rgba8UnormSrgb,
+        D::B8G8R8A8_UNorm => F::Bgra8Unorm,
+        D::B8G8R8A8_UNorm_sRGB => F::Bgra8UnormSrgb,
+        D::R16G16B16A16_Float => F::Rgba16Float,
+        D::R32G32B32A32_Float => F::Rgba32Float,
+        D::BC1_UNorm => F::Bc1RgbaUnorm,
+        D::BC1_UNorm_sRGB => F::Bc1RgbaUnormSrgb,
+        D::BC2_UNorm => F::Bc2RgbaUnorm,
+        D::BC2_UNorm_sRGB => F::Bc2RgbaUnormSrgb,
+        D::BC3_UNorm => F::Bc3RgbaUnorm,
+        D::BC3_UNorm_sRGB => F::Bc3RgbaUnormSrgb,
+        D::BC4_UNorm => F::Bc4RUnorm,
+        D::BC4_SNorm => F::Bc4RSnorm,
+        D::BC5_UNorm => F::Bc5RgUnorm,
+        D::BC5_SNorm => F::Bc5RgSnorm,
+        D::BC6H_UF16 => F::Bc6hRgbUfloat,
+        D::BC6H_SF16 => F::Bc6hRgbFloat,
+        D::BC7_UNorm => F::Bc7RgbaUnorm,
+        D::BC7_UNorm_sRGB => F::Bc7RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+fn read_dds(bytes: &[u8]) -> Result<ContainerTexture, ContainerError> {
+    let dds = ddsfile::Dds::read(bytes)?;
+
+    let format = dds_format(&dds).ok_or_else(|| {
+        ContainerError::Unsupported(format!(
+            "dds format {}",
+            dds.get_dxgi_format()
+                .map_or(String::from("unknown"), |format| format!("{format:?}"))
+        ))
+    })?;
+
+    let caps2 = dds.header.caps2;
+    let (volume, cube, array_size) = match &dds.header10 {
+        Some(header10) => (
+            header10.resource_dimension == ddsfile::D3D10ResourceDimension::Texture3D,
+            header10.misc_flag.contains(ddsfile::MiscFlag::TEXTURECUBE),
+            header10.array_size.max(1),
+        ),
+        None => (
+            caps2.contains(ddsfile::Caps2::VOLUME),
+            caps2.contains(ddsfile::Caps2::CUBEMAP),
+            1,
+        ),
+    };
+
+    // The dx10 header counts cubes rather than faces
+    let kind = match (volume, cube, array_size) {
+        (true, _, _) => TextureKind::D3 {
+            depth: dds.get_depth().max(1),
+        },
+        (false, true, 1) => TextureKind::Cube,
+        (false, true, cubes) => TextureKind::CubeArray { cubes },
+        (false, false, 1) => TextureKind::D2,
+        (false, false, layers) => TextureKind::D2Array { layers },
+    };
+
+    let dimensions = (dds.get_width(), dds.get_height());
+    let mip_level_count = dds.get_num_mipmap_levels().max(1);
+
+    // Dds stores the whole mip chain of a layer before the next layer, uploads want every
+    // layer of a level together
+    let images = match kind {
+        TextureKind::D3 { .. } => 1,
+        kind => kind.layers(),
+    };
+    let mut levels = vec![Vec::new(); mip_level_count as usize];
+    let mut offset = 0;
+
+    for _ in 0..images {
+        for (level, data) in levels.iter_mut().enumerate() {
+            let (size, layers) = level_extent(dimensions, kind, level as u32);
+            let slices = match kind {
+                TextureKind::D3 { .. } => layers,
+                _ => 1,
+            };
+
+            let size = data_size(format, size).ok_or(RenderError::UnsupportedFormat(format))?
+                * slices as usize;
+            let image =
+                dds.data
+                    .get(offset..offset + size)
+                    .ok_or(RenderError::InvalidDataSize {
+                        expected: offset + size,
+                        actual: dds.data.len(),
+                    })?;
+
+            data.extend_from_slice(image);
+            offset += size;
+        }
+    }
+
+    Ok(ContainerTexture {
+        format,
+        dimensions,
+        kind,
+        levels,
+    })
+}
+
+impl<'a> RenderingContext<'a> {
+    // Picks the container from the file's magic bytes
+    pub fn create_texture_from_container_file(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<TextureHandle, ContainerError> {
+        let bytes = std::fs::read(path)?;
+
+        match bytes.starts_with(b"DDS ") {
+            true => self.create_texture_from_dds(&bytes),
+            false => self.create_texture_from_ktx2(&bytes),
+        }
+    }
+
+    pub fn create_texture_from_ktx2(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<TextureHandle, ContainerError> {
+        let texture = read_ktx2(bytes)?;
+        self.create_container_texture(texture)
+    }
+
+    pub fn create_texture_from_dds(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<TextureHandle, ContainerError> {
+        let texture = read_dds(bytes)?;
+        self.create_container_texture(texture)
+    }
+
+    // Compressed formats the device can't sample get decoded into rgba8 on the cpu
+    fn create_container_texture(
+        &mut self,
+        texture: ContainerTexture,
+    ) -> Result<TextureHandle, ContainerError> {
+        let start = Instant::now();
+
+        let ContainerTexture {
+            mut format,
+            dimensions,
+            kind,
+            mut levels,
+        } = texture;
+
+        let cube_faces_square = !matches!(kind, TextureKind::Cube | TextureKind::CubeArray { .. })
+            || dimensions.0 == dimensions.1;
+        if dimensions.0 == 0
+            || dimensions.1 == 0
+            || kind.layers() == 0
+            || levels.is_empty()
+            || !cube_faces_square
+        {
+            return Err(RenderError::InvalidDimensions { format, dimensions }.into());
+        }
+
+        // Files with a longer chain than the texture can hold, wgpu would reject them
+        levels.truncate(full_mip_count(dimensions) as usize);
+
+        for (level, data) in levels.iter().enumerate() {
+            let (size, layers) = level_extent(dimensions, kind, level as u32);
+            let expected = data_size(format, size).ok_or(RenderError::UnsupportedFormat(format))?
+                * layers as usize;
+
+            if data.len() != expected {
+                return Err(RenderError::InvalidDataSize {
+                    expected,
+                    actual: data.len(),
+                }
+                .into());
+            }
+        }
+
+        if !self.device.features().contains(format.required_features()) {
+            // Hdr astc is the only compressed format without a cpu decoder
+            let decompressed = decompressed_format(format).ok_or_else(|| {
+                ContainerError::Unsupported(format!(
+                    "{format:?} texture, the device lacks {:?} and it can't be decompressed",
+                    format.required_features()
+                ))
+            })?;
+
+            for (level, data) in levels.iter_mut().enumerate() {
+                let (size, _) = level_extent(dimensions, kind, level as u32);
+                let layer_size = data_size(format, size).unwrap();
+
+                let mut decompressed_data = Vec::new();
+                for layer in data.chunks_exact(layer_size) {
+                    decompressed_data.extend(decompress(format, layer, size)?);
+                }
+                *data = decompressed_data;
+            }
+
+            crate::debug!("Decompressed {:?} texture on the cpu", format);
+            format = decompressed;
+        } else {
+            let (block_width, block_height) = format.block_dimensions();
+            if dimensions.0 % block_width != 0 || dimensions.1 % block_height != 0 {
+                return Err(RenderError::InvalidDimensions { format, dimensions }.into());
+            }
+        }
+
+        let texture = Texture::from_desc(
+            &self.device,
+            &self.queue,
+            &[],
+            TextureDesc {
+                dimensions,
+                kind,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                sampler_type: wgpu::FilterMode::Linear,
+                mip_level_count: levels.len() as u32,
+            },
+        );
+
+        for (level, data) in levels.iter().enumerate() {
+            texture.write_mip_level(&self.queue, level as u32, data);
+        }
+
+        self.record_cpu_time("texture upload", start);
+
+        Ok(self.textures.insert(texture))
+    }
+}
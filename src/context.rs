@@ -0,0 +1,269 @@
+use std::sync::Arc;
+
+use wgpu::{Features, TextureFormat};
+use winit::window::Window;
+
+use crate::renderer::RenderingContext;
+use crate::tonemap::HDR_FORMAT;
+
+#[derive(Debug)]
+pub enum ContextError {
+    CreateSurface(wgpu::CreateSurfaceError),
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+    // Required features the adapter doesn't support
+    MissingFeatures(Features),
+    IncompatibleSurface,
+}
+
+impl std::fmt::Display for ContextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContextError::CreateSurface(err) => write!(f, "couldn't create surface: {err}"),
+            ContextError::NoAdapter => write!(f, "no adapter matches the requested options"),
+            ContextError::RequestDevice(err) => write!(f, "couldn't create device: {err}"),
+            ContextError::MissingFeatures(features) => {
+                write!(f, "adapter doesn't support required features {features:?}")
+            }
+            ContextError::IncompatibleSurface => {
+                write!(f, "surface reports no formats for the adapter")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ContextError {}
+
+impl From<wgpu::CreateSurfaceError> for ContextError {
+    fn from(err: wgpu::CreateSurfaceError) -> Self {
+        ContextError::CreateSurface(err)
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for ContextError {
+    fn from(err: wgpu::RequestDeviceError) -> Self {
+        ContextError::RequestDevice(err)
+    }
+}
+
+pub struct ContextBuilder {
+    backends: wgpu::Backends,
+    instance_flags: wgpu::InstanceFlags,
+    power_preference: wgpu::PowerPreference,
+    force_fallback_adapter: bool,
+    required_features: Features,
+    optional_features: Features,
+    limits: wgpu::Limits,
+    present_mode: wgpu::PresentMode,
+    frame_latency: u32,
+    alpha_mode: wgpu::CompositeAlphaMode,
+    swapchain_format: Option<TextureFormat>,
+    hdr: bool,
+}
+
+impl Default for ContextBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ContextBuilder {
+    pub fn new() -> Self {
+        Self {
+            backends: wgpu::Backends::all(),
+            // Validation and debug labels only in debug builds, overridable with WGPU_DEBUG
+            // and WGPU_VALIDATION
+            instance_flags: wgpu::InstanceFlags::from_build_config().with_env(),
+            power_preference: wgpu::PowerPreference::LowPower,
+            force_fallback_adapter: false,
+            required_features: Features::empty(),
+            // Compressed formats are enabled whenever available, textures in formats the
+            // device lacks get decompressed on load instead. Adapter specific format
+            // features allow reading from storage textures, timestamp queries give the
+            // profiler gpu timings and the address modes allow border colors in samplers
+            optional_features: Features::MULTI_DRAW_INDIRECT
+                | Features::TIMESTAMP_QUERY
+                | Features::ADDRESS_MODE_CLAMP_TO_BORDER
+                | Features::ADDRESS_MODE_CLAMP_TO_ZERO
+                | Features::TEXTURE_COMPRESSION_BC
+                | Features::TEXTURE_COMPRESSION_ETC2
+                | Features::TEXTURE_COMPRESSION_ASTC
+                | Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+            limits: wgpu::Limits::downlevel_defaults(),
+            present_mode: wgpu::PresentMode::AutoVsync,
+            frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            swapchain_format: None,
+            hdr: false,
+        }
+    }
+
+    pub fn backends(mut self, backends: wgpu::Backends) -> Self {
+        self.backends = backends;
+        self
+    }
+
+    pub fn instance_flags(mut self, flags: wgpu::InstanceFlags) -> Self {
+        self.instance_flags = flags;
+        self
+    }
+
+    pub fn power_preference(mut self, preference: wgpu::PowerPreference) -> Self {
+        self.power_preference = preference;
+        self
+    }
+
+    pub fn force_fallback_adapter(mut self, force: bool) -> Self {
+        self.force_fallback_adapter = force;
+        self
+    }
+
+    // Building fails if the adapter lacks any of these
+    pub fn required_features(mut self, features: Features) -> Self {
+        self.required_features = features;
+        self
+    }
+
+    // Enabled when the adapter supports them, silently left out otherwise
+    pub fn optional_features(mut self, features: Features) -> Self {
+        self.optional_features = features;
+        self
+    }
+
+    pub fn limits(mut self, limits: wgpu::Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    // Falls back to Fifo when the surface doesn't support the mode
+    pub fn present_mode(mut self, mode: wgpu::PresentMode) -> Self {
+        self.present_mode = mode;
+        self
+    }
+
+    pub fn frame_latency(mut self, frames: u32) -> Self {
+        self.frame_latency = frames;
+        self
+    }
+
+    // Falls back to Auto when the surface doesn't support the mode
+    pub fn alpha_mode(mut self, mode: wgpu::CompositeAlphaMode) -> Self {
+        self.alpha_mode = mode;
+        self
+    }
+
+    // Used when the surface supports it, otherwise the first srgb format is picked
+    pub fn swapchain_format(mut self, format: TextureFormat) -> Self {
+        self.swapchain_format = Some(format);
+        self
+    }
+
+    // Render targets use HDR_FORMAT and the surface gets an extended range format when the
+    // adapter has one, otherwise display_tex tonemaps to the sdr surface
+    pub fn hdr(mut self, enabled: bool) -> Self {
+        self.hdr = enabled;
+        self
+    }
+
+    pub async fn build<'a>(
+        self,
+        window_size: impl Into<[u32; 2]>,
+        window: Arc<Window>,
+    ) -> Result<RenderingContext<'a>, ContextError> {
+        let window_size = window_size.into();
+
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            flags: self.instance_flags,
+            gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
+            backends: self.backends,
+            dx12_shader_compiler: wgpu::Dx12Compiler::Fxc,
+        });
+
+        let surface = instance.create_surface(window)?;
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: self.power_preference,
+                compatible_surface: Some(&surface),
+                force_fallback_adapter: self.force_fallback_adapter,
+            })
+            .await
+            .ok_or(ContextError::NoAdapter)?;
+
+        crate::debug!("Using adapter {:?}", adapter.get_info());
+
+        let missing = self.required_features - adapter.features();
+        if !missing.is_empty() {
+            return Err(ContextError::MissingFeatures(missing));
+        }
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("Indigo device"),
+                    required_features: self.required_features
+                        | (adapter.features() & self.optional_features),
+                    required_limits: self.limits,
+                },
+                None,
+            )
+            .await?;
+
+        let capabilities = surface.get_capabilities(&adapter);
+
+        let hdr_surface = self
+            .hdr
+            .then_some(HDR_FORMAT)
+            .filter(|format| capabilities.formats.contains(format));
+
+        let swapchain_format = self
+            .swapchain_format
+            .filter(|format| capabilities.formats.contains(format))
+            .or(hdr_surface)
+            .or_else(|| {
+                capabilities
+                    .formats
+                    .iter()
+                    .copied()
+                    .find(|format| format.is_srgb())
+            })
+            .or_else(|| capabilities.formats.first().copied())
+            .ok_or(ContextError::IncompatibleSurface)?;
+
+        // The auto modes are supported everywhere, Fifo is the only guaranteed explicit one
+        let present_mode = match self.present_mode {
+            wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync => self.present_mode,
+            mode if capabilities.present_modes.contains(&mode) => mode,
+            _ => wgpu::PresentMode::Fifo,
+        };
+
+        let alpha_mode = if capabilities.alpha_modes.contains(&self.alpha_mode) {
+            self.alpha_mode
+        } else {
+            wgpu::CompositeAlphaMode::Auto
+        };
+
+        let config = wgpu::SurfaceConfiguration {
+            desired_maximum_frame_latency: self.frame_latency,
+            alpha_mode,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: swapchain_format,
+            width: window_size[0],
+            height: window_size[1],
+            present_mode,
+            view_formats: vec![],
+        };
+
+        Ok(RenderingContext::from_parts(
+            instance,
+            surface,
+            adapter,
+            device,
+            queue,
+            config,
+            match self.hdr {
+                true => HDR_FORMAT,
+                false => swapchain_format,
+            },
+        ))
+    }
+}
@@ -0,0 +1,1263 @@
+use std::array;
+
+use crate::astc;
+use crate::renderer::RenderError;
+
+// Cpu decoders for block compressed formats, used when the device lacks the feature to sample
+// them directly. Unorm formats decode into rgba8 and signed ones into rgba8 snorm, single and
+// two channel formats keep their values in red and green. Bc6h decodes into rgba16 float.
+// Astc is limited to the ldr formats, hdr blocks decode to the error color
+
+// Texels of a 4x4 block, row by row
+type Block = [[u8; 4]; 16];
+
+// Bc6h texels as f16 bits
+type HalfBlock = [[u16; 4]; 16];
+
+enum Decoder {
+    Rgba8(fn(&[u8]) -> Block),
+    Rgba16Float(fn(&[u8]) -> HalfBlock),
+    // Block footprint and whether the format is srgb
+    Astc((u32, u32), bool),
+}
+
+impl Decoder {
+    // Writes the texels of a block row by row into the scratch buffer
+    fn decode(&self, block: &[u8], texels: &mut [u8]) {
+        match self {
+            Decoder::Rgba8(decode) => texels.copy_from_slice(decode(block).as_flattened()),
+            Decoder::Rgba16Float(decode) => {
+                for (texel, value) in texels.chunks_exact_mut(2).zip(decode(block).as_flattened()) {
+                    texel.copy_from_slice(&value.to_le_bytes());
+                }
+            }
+            Decoder::Astc(footprint, srgb) => astc::decode_block(block, *footprint, *srgb, texels),
+        }
+    }
+}
+
+fn block_decoder(format: wgpu::TextureFormat) -> Option<Decoder> {
+    use wgpu::{AstcChannel, TextureFormat as F};
+
+    Some(Decoder::Rgba8(match format {
+        F::Bc1RgbaUnorm | F::Bc1RgbaUnormSrgb => decode_bc1,
+        F::Bc2RgbaUnorm | F::Bc2RgbaUnormSrgb => decode_bc2,
+        F::Bc3RgbaUnorm | F::Bc3RgbaUnormSrgb => decode_bc3,
+        F::Bc4RUnorm => decode_bc4,
+        F::Bc4RSnorm => decode_bc4_signed,
+        F::Bc5RgUnorm => decode_bc5,
+        F::Bc5RgSnorm => decode_bc5_signed,
+        F::Bc6hRgbUfloat => return Some(Decoder::Rgba16Float(decode_bc6h)),
+        F::Bc6hRgbFloat => return Some(Decoder::Rgba16Float(decode_bc6h_signed)),
+        F::Bc7RgbaUnorm | F::Bc7RgbaUnormSrgb => decode_bc7,
+        F::Etc2Rgb8Unorm | F::Etc2Rgb8UnormSrgb => decode_etc2_rgb,
+        F::Etc2Rgb8A1Unorm | F::Etc2Rgb8A1UnormSrgb => decode_etc2_rgb_a1,
+        F::Etc2Rgba8Unorm | F::Etc2Rgba8UnormSrgb => decode_etc2_rgba,
+        F::EacR11Unorm => decode_eac_r11,
+        F::EacR11Snorm => decode_eac_r11_signed,
+        F::EacRg11Unorm => decode_eac_rg11,
+        F::EacRg11Snorm => decode_eac_rg11_signed,
+        F::Astc { channel, .. } if channel != AstcChannel::Hdr => {
+            return Some(Decoder::Astc(format.block_dimensions(), format.is_srgb()));
+        }
+        _ => return None,
+    }))
+}
+
+// Format decompressed data gets uploaded as, None for formats without a decoder (hdr astc)
+pub fn decompressed_format(format: wgpu::TextureFormat) -> Option<wgpu::TextureFormat> {
+    use wgpu::TextureFormat as F;
+
+    Some(match block_decoder(format)? {
+        Decoder::Rgba16Float(_) => F::Rgba16Float,
+        _ if format.is_srgb() => F::Rgba8UnormSrgb,
+        _ => match format {
+            F::Bc4RSnorm | F::Bc5RgSnorm | F::EacR11Snorm | F::EacRg11Snorm => F::Rgba8Snorm,
+            _ => F::Rgba8Unorm,
+        },
+    })
+}
+
+// Decodes a single layer of tightly packed blocks into tightly packed rows of the
+// decompressed format
+pub fn decompress(
+    format: wgpu::TextureFormat,
+    data: &[u8],
+    dimensions: (u32, u32),
+) -> Result<Vec<u8>, RenderError> {
+    let decoder = block_decoder(format).ok_or(RenderError::UnsupportedFormat(format))?;
+    let texel_size = decompressed_format(format)
+        .and_then(|format| format.block_copy_size(None))
+        .unwrap() as usize;
+    let block_size = format.block_copy_size(None).unwrap() as usize;
+    let (block_width, block_height) = format.block_dimensions();
+    let (block_width, block_height) = (block_width as usize, block_height as usize);
+
+    let (width, height) = (dimensions.0 as usize, dimensions.1 as usize);
+    let blocks_x = width.div_ceil(block_width);
+    let blocks_y = height.div_ceil(block_height);
+
+    let expected = blocks_x * blocks_y * block_size;
+    if data.len() != expected {
+        return Err(RenderError::InvalidDataSize {
+            expected,
+            actual: data.len(),
+        });
+    }
+
+    let mut pixels = vec![0; width * height * texel_size];
+    let mut texels = vec![0; block_width * block_height * texel_size];
+    let row_size = block_width * texel_size;
+
+    for (i, block) in data.chunks_exact(block_size).enumerate() {
+        let (block_x, block_y) = (i % blocks_x * block_width, i / blocks_x * block_height);
+        decoder.decode(block, &mut texels);
+
+        // Blocks on the right and bottom edge can hang over the texture
+        let columns = block_width.min(width - block_x);
+        for (row, texels) in texels.chunks_exact(row_size).enumerate() {
+            let y = block_y + row;
+            if y < height {
+                let offset = (y * width + block_x) * texel_size;
+                pixels[offset..offset + columns * texel_size]
+                    .copy_from_slice(&texels[..columns * texel_size]);
+            }
+        }
+    }
+
+    Ok(pixels)
+}
+
+fn mix(a: [u8; 4], b: [u8; 4], weight: u32, total: u32) -> [u8; 4] {
+    array::from_fn(|i| ((a[i] as u32 * (total - weight) + b[i] as u32 * weight) / total) as u8)
+}
+
+fn rgb565(color: u16) -> [u8; 4] {
+    let r = (color >> 11) as u8 & 31;
+    let g = (color >> 5) as u8 & 63;
+    let b = color as u8 & 31;
+
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2, 255]
+}
+
+// Color half of bc1-3. Only bc1 has the three color mode with a transparent texel
+fn decode_bc1_color(block: &[u8], punchthrough: bool) -> Block {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let (a, b) = (rgb565(color0), rgb565(color1));
+
+    let palette = match punchthrough && color0 <= color1 {
+        true => [a, b, mix(a, b, 1, 2), [0; 4]],
+        false => [a, b, mix(a, b, 1, 3), mix(a, b, 2, 3)],
+    };
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    array::from_fn(|i| palette[(indices >> (i * 2)) as usize & 3])
+}
+
+// Interpolated single channel block of bc3 alpha, bc4 and bc5. Signed endpoints are two's
+// complement with -128 treated like -127, their texels come out as snorm bytes
+fn decode_bc4_channel(block: &[u8], signed: bool) -> [u8; 16] {
+    let endpoint = |byte: u8| match signed {
+        true => (byte as i8).max(-127) as i32,
+        false => byte as i32,
+    };
+    let (a0, a1) = (endpoint(block[0]), endpoint(block[1]));
+    let (min, max) = match signed {
+        true => (-127, 127),
+        false => (0, 255),
+    };
+
+    let palette: [u8; 8] = array::from_fn(|i| {
+        let i = i as i32;
+        (match i {
+            0 => a0,
+            1 => a1,
+            _ if a0 > a1 => ((8 - i) * a0 + (i - 1) * a1) / 7,
+            6 => min,
+            7 => max,
+            _ => ((6 - i) * a0 + (i - 1) * a1) / 5,
+        }) as u8
+    });
+
+    let indices = block[2..8]
+        .iter()
+        .rev()
+        .fold(0u64, |bits, byte| bits << 8 | *byte as u64);
+    array::from_fn(|i| palette[(indices >> (i * 3)) as usize & 7])
+}
+
+fn decode_bc1(block: &[u8]) -> Block {
+    decode_bc1_color(block, true)
+}
+
+fn decode_bc2(block: &[u8]) -> Block {
+    let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+    let mut texels = decode_bc1_color(&block[8..], false);
+
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[3] = (alpha >> (i * 4)) as u8 & 15;
+        texel[3] *= 17;
+    }
+
+    texels
+}
+
+fn decode_bc3(block: &[u8]) -> Block {
+    let alpha = decode_bc4_channel(&block[..8], false);
+    let mut texels = decode_bc1_color(&block[8..], false);
+
+    for (texel, alpha) in texels.iter_mut().zip(alpha) {
+        texel[3] = alpha;
+    }
+
+    texels
+}
+
+fn decode_bc4(block: &[u8]) -> Block {
+    decode_bc4_channel(block, false).map(|r| [r, 0, 0, 255])
+}
+
+fn decode_bc4_signed(block: &[u8]) -> Block {
+    decode_bc4_channel(block, true).map(|r| [r, 0, 0, 127])
+}
+
+fn decode_bc5(block: &[u8]) -> Block {
+    let red = decode_bc4_channel(&block[..8], false);
+    let green = decode_bc4_channel(&block[8..], false);
+
+    array::from_fn(|i| [red[i], green[i], 0, 255])
+}
+
+fn decode_bc5_signed(block: &[u8]) -> Block {
+    let red = decode_bc4_channel(&block[..8], true);
+    let green = decode_bc4_channel(&block[8..], true);
+
+    array::from_fn(|i| [red[i], green[i], 0, 127])
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    secondary_index_bits: u32,
+}
+
+#[allow(clippy::too_many_arguments)]
+const fn bc7_mode(
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    pbits: (bool, bool),
+    index_bits: (u32, u32),
+) -> Bc7Mode {
+    Bc7Mode {
+        subsets,
+        partition_bits,
+        rotation_bits,
+        index_selection_bits,
+        color_bits,
+        alpha_bits,
+        endpoint_pbits: pbits.0,
+        shared_pbits: pbits.1,
+        index_bits: index_bits.0,
+        secondary_index_bits: index_bits.1,
+    }
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    bc7_mode(3, 4, 0, 0, 4, 0, (true, false), (3, 0)),
+    bc7_mode(2, 6, 0, 0, 6, 0, (false, true), (3, 0)),
+    bc7_mode(3, 6, 0, 0, 5, 0, (false, false), (2, 0)),
+    bc7_mode(2, 6, 0, 0, 7, 0, (true, false), (2, 0)),
+    bc7_mode(1, 0, 2, 1, 5, 6, (false, false), (2, 3)),
+    bc7_mode(1, 0, 2, 0, 7, 8, (false, false), (2, 2)),
+    bc7_mode(1, 0, 0, 0, 7, 7, (true, false), (4, 0)),
+    bc7_mode(2, 6, 0, 0, 5, 5, (true, false), (2, 0)),
+];
+
+// Two subset partitions, a set bit puts the texel into the second subset
+const BC7_PARTITIONS_2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80, 0xc800, 0xffec, 0xfe80, 0xe800,
+    0xffe8, 0xff00, 0xfff0, 0xf000, 0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c, 0xaaaa, 0xf0f0, 0x5a5a, 0x33cc,
+    0x3c3c, 0x55aa, 0x9696, 0xa55a, 0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c, 0x9336, 0x9cc6, 0x817e, 0xe718,
+    0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+#[rustfmt::skip]
+const BC7_PARTITIONS_3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2], [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1], [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2], [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2], [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2], [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2], [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2], [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0], [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0], [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2], [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1], [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2], [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0], [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0], [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1], [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1], [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1], [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1], [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2], [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2], [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2], [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1], [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2], [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+// Texels whose index is stored with one bit less, the first texel of a subset is implied
+const BC7_ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2,
+    2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+const BC7_ANCHORS_3: [[u8; 64]; 2] = [
+    [
+        3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6,
+        8, 5, 15, 15, 8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, 3, 15, 5, 5, 5, 8,
+        5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+    ],
+    [
+        15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, 15, 8, 15, 3, 15, 8, 15, 8, 3,
+        15, 6, 10, 15, 15, 10, 8, 15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, 15, 3, 15,
+        15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+    ],
+];
+
+fn bc7_weights(index_bits: u32) -> &'static [u32] {
+    match index_bits {
+        2 => &[0, 21, 43, 64],
+        3 => &[0, 9, 18, 27, 37, 46, 55, 64],
+        _ => &[0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64],
+    }
+}
+
+// Reads the block from its lowest bit up
+struct BitReader {
+    bits: u128,
+}
+
+impl BitReader {
+    fn read(&mut self, count: u32) -> u32 {
+        let value = self.bits as u32 & ((1 << count) - 1);
+        self.bits >>= count;
+        value
+    }
+}
+
+fn decode_bc7(block: &[u8]) -> Block {
+    let mut bits = BitReader {
+        bits: u128::from_le_bytes(block[..16].try_into().unwrap()),
+    };
+
+    // Reserved mode, decodes to transparent black
+    let mode_index = block[0].trailing_zeros();
+    if mode_index >= 8 {
+        return [[0; 4]; 16];
+    }
+    bits.read(mode_index + 1);
+
+    let mode = &BC7_MODES[mode_index as usize];
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+
+    for channel in 0..3 {
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            endpoint[channel] = bits.read(mode.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        endpoint[3] = bits.read(mode.alpha_bits);
+    }
+
+    let mut pbits = [0; 6];
+    if mode.endpoint_pbits {
+        pbits
+            .iter_mut()
+            .take(endpoint_count)
+            .for_each(|pbit| *pbit = bits.read(1));
+    }
+    if mode.shared_pbits {
+        for subset in 0..mode.subsets {
+            let pbit = bits.read(1);
+            pbits[subset * 2] = pbit;
+            pbits[subset * 2 + 1] = pbit;
+        }
+    }
+
+    let has_pbits = mode.endpoint_pbits || mode.shared_pbits;
+    let color_bits = mode.color_bits + has_pbits as u32;
+    let alpha_bits = mode.alpha_bits + has_pbits as u32;
+
+    // Endpoints get their pbit appended and the top bits repeated down to 8 bits
+    let expand = |value: u32, pbit: u32, bits: u32| {
+        let value = match has_pbits {
+            true => value << 1 | pbit,
+            false => value,
+        } << (8 - bits);
+        (value | value >> bits) as u8
+    };
+    let endpoints: [[u8; 4]; 6] = array::from_fn(|i| {
+        let endpoint = endpoints[i];
+        [
+            expand(endpoint[0], pbits[i], color_bits),
+            expand(endpoint[1], pbits[i], color_bits),
+            expand(endpoint[2], pbits[i], color_bits),
+            match mode.alpha_bits {
+                0 => 255,
+                _ => expand(endpoint[3], pbits[i], alpha_bits),
+            },
+        ]
+    });
+
+    let subset = |texel: usize| match mode.subsets {
+        1 => 0,
+        2 => (BC7_PARTITIONS_2[partition] >> texel) as usize & 1,
+        _ => BC7_PARTITIONS_3[partition][texel] as usize,
+    };
+    let anchor = |texel: usize| {
+        texel == 0
+            || match mode.subsets {
+                2 => texel == BC7_ANCHORS_2[partition] as usize,
+                3 => BC7_ANCHORS_3
+                    .iter()
+                    .any(|anchors| texel == anchors[partition] as usize),
+                _ => false,
+            }
+    };
+
+    let indices: [u32; 16] = array::from_fn(|i| bits.read(mode.index_bits - anchor(i) as u32));
+    let secondary_indices: [u32; 16] = array::from_fn(|i| match mode.secondary_index_bits {
+        0 => 0,
+        bits_count => bits.read(bits_count - (i == 0) as u32),
+    });
+
+    array::from_fn(|i| {
+        let subset = subset(i);
+        let (e0, e1) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+
+        // Modes 4 and 5 index color and alpha separately, the selection bit swaps the two
+        let primary = bc7_weights(mode.index_bits)[indices[i] as usize];
+        let (color_weight, alpha_weight) = match mode.secondary_index_bits {
+            0 => (primary, primary),
+            bits_count => {
+                let secondary = bc7_weights(bits_count)[secondary_indices[i] as usize];
+                match index_selection {
+                    0 => (primary, secondary),
+                    _ => (secondary, primary),
+                }
+            }
+        };
+
+        let lerp = |channel: usize, weight: u32| {
+            (((64 - weight) * e0[channel] as u32 + weight * e1[channel] as u32 + 32) >> 6) as u8
+        };
+        let mut texel = [
+            lerp(0, color_weight),
+            lerp(1, color_weight),
+            lerp(2, color_weight),
+            lerp(3, alpha_weight),
+        ];
+
+        match rotation {
+            1 => texel.swap(0, 3),
+            2 => texel.swap(1, 3),
+            3 => texel.swap(2, 3),
+            _ => {}
+        }
+
+        texel
+    })
+}
+
+// Bc6h endpoint fields, the four endpoints of the two regions with their rgb channels
+const R0: u8 = 0;
+const G0: u8 = 1;
+const B0: u8 = 2;
+const R1: u8 = 3;
+const G1: u8 = 4;
+const B1: u8 = 5;
+const R2: u8 = 6;
+const G2: u8 = 7;
+const B2: u8 = 8;
+const R3: u8 = 9;
+const G3: u8 = 10;
+const B3: u8 = 11;
+
+struct Bc6hMode {
+    regions: usize,
+    transformed: bool,
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    // Fields in the order they are stored, written like the spec does. (field, a, b) is
+    // field[a:b], read starting at bit b and stepping towards a
+    layout: &'static [(u8, u8, u8)],
+}
+
+#[rustfmt::skip]
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode {
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 10,
+        delta_bits: [5, 5, 5],
+        layout: &[
+            (G2, 4, 4), (B2, 4, 4), (B3, 4, 4), (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 4, 0),
+            (G3, 4, 4), (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1),
+            (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 7,
+        delta_bits: [6, 6, 6],
+        layout: &[
+            (G2, 5, 5), (G3, 4, 4), (G3, 5, 5), (R0, 6, 0), (B3, 0, 0), (B3, 1, 1), (B2, 4, 4),
+            (G0, 6, 0), (B2, 5, 5), (B3, 2, 2), (G2, 4, 4), (B0, 6, 0), (B3, 3, 3), (B3, 5, 5),
+            (B3, 4, 4), (R1, 5, 0), (G2, 3, 0), (G1, 5, 0), (G3, 3, 0), (B1, 5, 0), (B2, 3, 0),
+            (R2, 5, 0), (R3, 5, 0),
+        ],
+    },
+    Bc6hMode {
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [5, 4, 4],
+        layout: &[
+            (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 4, 0), (R0, 10, 10), (G2, 3, 0), (G1, 3, 0),
+            (G0, 10, 10), (B3, 0, 0), (G3, 3, 0), (B1, 3, 0), (B0, 10, 10), (B3, 1, 1),
+            (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 5, 4],
+        layout: &[
+            (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 3, 0), (R0, 10, 10), (G3, 4, 4), (G2, 3, 0),
+            (G1, 4, 0), (G0, 10, 10), (G3, 3, 0), (B1, 3, 0), (B0, 10, 10), (B3, 1, 1),
+            (B2, 3, 0), (R2, 3, 0), (B3, 0, 0), (B3, 2, 2), (R3, 3, 0), (G2, 4, 4), (B3, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 4, 5],
+        layout: &[
+            (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 3, 0), (R0, 10, 10), (B2, 4, 4), (G2, 3, 0),
+            (G1, 3, 0), (G0, 10, 10), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B0, 10, 10),
+            (B2, 3, 0), (R2, 3, 0), (B3, 1, 1), (B3, 2, 2), (R3, 3, 0), (B3, 4, 4), (B3, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 9,
+        delta_bits: [5, 5, 5],
+        layout: &[
+            (R0, 8, 0), (B2, 4, 4), (G0, 8, 0), (G2, 4, 4), (B0, 8, 0), (B3, 4, 4), (R1, 4, 0),
+            (G3, 4, 4), (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1),
+            (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [6, 5, 5],
+        layout: &[
+            (R0, 7, 0), (G3, 4, 4), (B2, 4, 4), (G0, 7, 0), (B3, 2, 2), (G2, 4, 4), (B0, 7, 0),
+            (B3, 3, 3), (B3, 4, 4), (R1, 5, 0), (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0),
+            (B1, 4, 0), (B3, 1, 1), (B2, 3, 0), (R2, 5, 0), (R3, 5, 0),
+        ],
+    },
+    Bc6hMode {
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 6, 5],
+        layout: &[
+            (R0, 7, 0), (B3, 0, 0), (B2, 4, 4), (G0, 7, 0), (G2, 5, 5), (G2, 4, 4), (B0, 7, 0),
+            (G3, 5, 5), (B3, 4, 4), (R1, 4, 0), (G3, 4, 4), (G2, 3, 0), (G1, 5, 0), (G3, 3, 0),
+            (B1, 4, 0), (B3, 1, 1), (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 5, 6],
+        layout: &[
+            (R0, 7, 0), (B3, 1, 1), (B2, 4, 4), (G0, 7, 0), (B2, 5, 5), (G2, 4, 4), (B0, 7, 0),
+            (B3, 5, 5), (B3, 4, 4), (R1, 4, 0), (G3, 4, 4), (G2, 3, 0), (G1, 4, 0), (B3, 0, 0),
+            (G3, 3, 0), (B1, 5, 0), (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        regions: 2,
+        transformed: false,
+        endpoint_bits: 6,
+        delta_bits: [6, 6, 6],
+        layout: &[
+            (R0, 5, 0), (G3, 4, 4), (B3, 0, 0), (B3, 1, 1), (B2, 4, 4), (G0, 5, 0), (G2, 5, 5),
+            (B2, 5, 5), (B3, 2, 2), (G2, 4, 4), (B0, 5, 0), (G3, 5, 5), (B3, 3, 3), (B3, 5, 5),
+            (B3, 4, 4), (R1, 5, 0), (G2, 3, 0), (G1, 5, 0), (G3, 3, 0), (B1, 5, 0), (B2, 3, 0),
+            (R2, 5, 0), (R3, 5, 0),
+        ],
+    },
+    Bc6hMode {
+        regions: 1,
+        transformed: false,
+        endpoint_bits: 10,
+        delta_bits: [10, 10, 10],
+        layout: &[(R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 9, 0), (G1, 9, 0), (B1, 9, 0)],
+    },
+    Bc6hMode {
+        regions: 1,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [9, 9, 9],
+        layout: &[
+            (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 8, 0), (R0, 10, 10), (G1, 8, 0),
+            (G0, 10, 10), (B1, 8, 0), (B0, 10, 10),
+        ],
+    },
+    Bc6hMode {
+        regions: 1,
+        transformed: true,
+        endpoint_bits: 12,
+        delta_bits: [8, 8, 8],
+        layout: &[
+            (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 7, 0), (R0, 10, 11), (G1, 7, 0),
+            (G0, 10, 11), (B1, 7, 0), (B0, 10, 11),
+        ],
+    },
+    Bc6hMode {
+        regions: 1,
+        transformed: true,
+        endpoint_bits: 16,
+        delta_bits: [4, 4, 4],
+        layout: &[
+            (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 3, 0), (R0, 10, 15), (G1, 3, 0),
+            (G0, 10, 15), (B1, 3, 0), (B0, 10, 15),
+        ],
+    },
+];
+
+fn decode_bc6h(block: &[u8]) -> HalfBlock {
+    decode_bc6h_block(block, false)
+}
+
+fn decode_bc6h_signed(block: &[u8]) -> HalfBlock {
+    decode_bc6h_block(block, true)
+}
+
+fn decode_bc6h_block(block: &[u8], signed: bool) -> HalfBlock {
+    const ONE: u16 = 0x3c00;
+
+    let mut bits = BitReader {
+        bits: u128::from_le_bytes(block[..16].try_into().unwrap()),
+    };
+
+    // Two bit modes first, the rest use five. Reserved modes decode to black
+    let mode = match bits.read(2) {
+        low @ (0 | 1) => low as usize,
+        low => match bits.read(3) << 2 | low {
+            high @ 0x02..=0x1e if high & 3 == 2 => 2 + (high >> 2) as usize,
+            high @ 0x03..=0x0f => 10 + (high >> 2) as usize,
+            _ => return [[0, 0, 0, ONE]; 16],
+        },
+    };
+    let mode = &BC6H_MODES[mode];
+
+    let mut endpoints = [[0i32; 3]; 4];
+    for &(field, a, b) in mode.layout {
+        let value = &mut endpoints[field as usize / 3][field as usize % 3];
+        let mut bit = b;
+        loop {
+            *value |= (bits.read(1) as i32) << bit;
+            if bit == a {
+                break;
+            }
+            bit = if a > b { bit + 1 } else { bit - 1 };
+        }
+    }
+    let partition = match mode.regions {
+        2 => bits.read(5) as usize,
+        _ => 0,
+    };
+
+    // Signed formats sign extend the endpoints, transformed modes store the other endpoints
+    // as signed deltas to the first one
+    let sign_extend = |value: i32, bits: u32| (value << (32 - bits)) >> (32 - bits);
+    let endpoint_bits = mode.endpoint_bits;
+    if signed {
+        endpoints[0] = endpoints[0].map(|value| sign_extend(value, endpoint_bits));
+    }
+    let (base, others) = endpoints.split_at_mut(1);
+    for endpoint in others.iter_mut().take(mode.regions * 2 - 1) {
+        for (channel, value) in endpoint.iter_mut().enumerate() {
+            if mode.transformed {
+                let delta = sign_extend(*value, mode.delta_bits[channel]);
+                *value = (base[0][channel] + delta) & ((1 << endpoint_bits) - 1);
+            }
+            if signed {
+                *value = sign_extend(*value, endpoint_bits);
+            }
+        }
+    }
+
+    // Scales the endpoints up to 16 bits, the interpolated values then get scaled into the
+    // finite half float range
+    let unquantize = |value: i32| match signed {
+        false if endpoint_bits >= 15 => value,
+        false if value == 0 => 0,
+        false if value == (1 << endpoint_bits) - 1 => 0xffff,
+        false => ((value << 16) + 0x8000) >> endpoint_bits,
+        true if endpoint_bits >= 16 => value,
+        true => {
+            let magnitude = match value.abs() {
+                0 => 0,
+                abs if abs >= (1 << (endpoint_bits - 1)) - 1 => 0x7fff,
+                abs => ((abs << 15) + 0x4000) >> (endpoint_bits - 1),
+            };
+            magnitude * value.signum()
+        }
+    };
+    let finish = |value: i32| match signed {
+        true => ((value < 0) as u16) << 15 | ((value.abs() * 31) >> 5) as u16,
+        false => ((value * 31) >> 6) as u16,
+    };
+    let endpoints = endpoints.map(|endpoint| endpoint.map(unquantize));
+
+    let index_bits = match mode.regions {
+        2 => 3,
+        _ => 4,
+    };
+    let anchor = |texel: usize| {
+        texel == 0 || (mode.regions == 2 && texel == BC7_ANCHORS_2[partition] as usize)
+    };
+    let indices: [u32; 16] = array::from_fn(|i| bits.read(index_bits - anchor(i) as u32));
+
+    array::from_fn(|i| {
+        let region = match mode.regions {
+            2 => (BC7_PARTITIONS_2[partition] >> i) as usize & 1,
+            _ => 0,
+        };
+        let (e0, e1) = (endpoints[region * 2], endpoints[region * 2 + 1]);
+        let weight = bc7_weights(index_bits)[indices[i] as usize] as i32;
+
+        let lerp =
+            |channel: usize| finish(((64 - weight) * e0[channel] + weight * e1[channel] + 32) >> 6);
+        [lerp(0), lerp(1), lerp(2), ONE]
+    })
+}
+
+const ETC_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+fn extend_4(value: u32) -> i32 {
+    (value * 17) as i32
+}
+
+fn extend_5(value: u32) -> i32 {
+    (value << 3 | value >> 2) as i32
+}
+
+fn clamp_color(color: [i32; 3]) -> [u8; 4] {
+    let [r, g, b] = color.map(|channel| channel.clamp(0, 255) as u8);
+    [r, g, b, 255]
+}
+
+fn offset_color(color: [i32; 3], offset: i32) -> [u8; 4] {
+    clamp_color(color.map(|channel| channel + offset))
+}
+
+// Etc1 compatible individual and differential blocks plus the t, h and planar modes etc2
+// hides in overflowing differential colors. Punchthrough blocks have no individual mode,
+// the bit marks whether the block has transparent texels instead
+fn decode_etc2_color(block: &[u8], punchthrough: bool) -> Block {
+    let bits = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let field = |offset: u32, count: u32| (bits >> offset) as u32 & ((1 << count) - 1);
+
+    let differential_bit = field(33, 1) == 1;
+    let opaque = !punchthrough || differential_bit;
+    let differential = punchthrough || differential_bit;
+
+    // Texel indices are stored column by column, high bits in the upper half
+    let index = |i: usize| {
+        let i = (i % 4) * 4 + i / 4;
+        field(i as u32 + 16, 1) << 1 | field(i as u32, 1)
+    };
+    let transparent = |index: u32| !opaque && index == 2;
+
+    let paint = |colors: [[u8; 4]; 4]| -> Block {
+        array::from_fn(|i| match transparent(index(i)) {
+            true => [0; 4],
+            false => colors[index(i) as usize],
+        })
+    };
+
+    let signed_3 = |value: u32| ((value << 29) as i32) >> 29;
+    let (r, g, b) = (
+        field(59, 5) as i32,
+        field(51, 5) as i32,
+        field(43, 5) as i32,
+    );
+    let (dr, dg, db) = (
+        signed_3(field(56, 3)),
+        signed_3(field(48, 3)),
+        signed_3(field(40, 3)),
+    );
+
+    let base_colors = match differential {
+        false => [
+            [field(60, 4), field(52, 4), field(44, 4)].map(extend_4),
+            [field(56, 4), field(48, 4), field(40, 4)].map(extend_4),
+        ],
+        true if !(0..32).contains(&(r + dr)) => {
+            let color1 = [field(59, 2) << 2 | field(56, 2), field(52, 4), field(48, 4)];
+            let color2 = [field(44, 4), field(40, 4), field(36, 4)];
+            let distance = ETC_DISTANCES[(field(34, 2) << 1 | field(32, 1)) as usize];
+
+            let (color1, color2) = (color1.map(extend_4), color2.map(extend_4));
+            return paint([
+                clamp_color(color1),
+                offset_color(color2, distance),
+                clamp_color(color2),
+                offset_color(color2, -distance),
+            ]);
+        }
+        true if !(0..32).contains(&(g + dg)) => {
+            let color1 = [
+                field(59, 4),
+                field(56, 3) << 1 | field(52, 1),
+                field(51, 1) << 3 | field(47, 3),
+            ];
+            let color2 = [field(43, 4), field(39, 4), field(35, 4)];
+
+            // The order of the two colors is the lowest bit of the distance index
+            let value = |color: [u32; 3]| color[0] << 8 | color[1] << 4 | color[2];
+            let distance_index =
+                field(34, 1) << 2 | field(32, 1) << 1 | (value(color1) >= value(color2)) as u32;
+            let distance = ETC_DISTANCES[distance_index as usize];
+
+            let (color1, color2) = (color1.map(extend_4), color2.map(extend_4));
+            return paint([
+                offset_color(color1, distance),
+                offset_color(color1, -distance),
+                offset_color(color2, distance),
+                offset_color(color2, -distance),
+            ]);
+        }
+        true if !(0..32).contains(&(b + db)) => {
+            let extend_6 = |value: u32| (value << 2 | value >> 4) as i32;
+            let extend_7 = |value: u32| (value << 1 | value >> 6) as i32;
+
+            let origin = [
+                extend_6(field(57, 6)),
+                extend_7(field(56, 1) << 6 | field(49, 6)),
+                extend_6(field(48, 1) << 5 | field(43, 2) << 3 | field(39, 3)),
+            ];
+            let horizontal = [
+                extend_6(field(34, 5) << 1 | field(32, 1)),
+                extend_7(field(25, 7)),
+                extend_6(field(19, 6)),
+            ];
+            let vertical = [
+                extend_6(field(13, 6)),
+                extend_7(field(6, 7)),
+                extend_6(field(0, 6)),
+            ];
+
+            return array::from_fn(|i| {
+                let (x, y) = ((i % 4) as i32, (i / 4) as i32);
+                clamp_color(array::from_fn(|c| {
+                    (x * (horizontal[c] - origin[c])
+                        + y * (vertical[c] - origin[c])
+                        + 4 * origin[c]
+                        + 2)
+                        >> 2
+                }))
+            });
+        }
+        true => [
+            [r, g, b].map(|value| extend_5(value as u32)),
+            [r + dr, g + dg, b + db].map(|value| extend_5(value as u32)),
+        ],
+    };
+
+    // Two halves side by side, or on top of each other when flipped
+    let flip = field(32, 1) == 1;
+    let tables = [field(37, 3), field(34, 3)];
+
+    array::from_fn(|i| {
+        let (x, y) = (i % 4, i / 4);
+        let half = match flip {
+            true => y >= 2,
+            false => x >= 2,
+        } as usize;
+
+        let index = index(i);
+        let [small, large] = ETC_MODIFIERS[tables[half] as usize];
+        let modifier = match index {
+            0 if !opaque => 0,
+            0 => small,
+            1 => large,
+            2 => -small,
+            _ => -large,
+        };
+
+        match transparent(index) {
+            true => [0; 4],
+            false => offset_color(base_colors[half], modifier),
+        }
+    })
+}
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+// Single channel block of etc2 alpha and the 11 bit r and rg formats, which get cut down
+// to 8 bits. Signed blocks have a two's complement base and come out as snorm bytes
+fn decode_eac_channel(block: &[u8], eleven_bit: bool, signed: bool) -> [u8; 16] {
+    let bits = u64::from_be_bytes(block[..8].try_into().unwrap());
+
+    let base = match signed {
+        true => ((bits >> 56) as i8).max(-127) as i32,
+        false => (bits >> 56) as i32,
+    };
+    let multiplier = (bits >> 52) as i32 & 15;
+    let modifiers = EAC_MODIFIERS[(bits >> 48) as usize & 15];
+
+    array::from_fn(|i| {
+        // Indices are stored column by column, first texel in the highest bits
+        let texel = (i % 4) * 4 + i / 4;
+        let modifier = modifiers[(bits >> (45 - texel * 3)) as usize & 7];
+
+        match (eleven_bit, signed) {
+            (true, true) => {
+                let value = match multiplier {
+                    0 => base * 8 + modifier,
+                    _ => base * 8 + modifier * multiplier * 8,
+                }
+                .clamp(-1023, 1023)
+                    * 127;
+                ((value + value.signum() * 511) / 1023) as i8 as u8
+            }
+            (true, false) => {
+                let value = match multiplier {
+                    0 => base * 8 + 4 + modifier,
+                    _ => base * 8 + 4 + modifier * multiplier * 8,
+                };
+                ((value.clamp(0, 2047) * 255 + 1023) / 2047) as u8
+            }
+            (false, _) => (base + modifier * multiplier).clamp(0, 255) as u8,
+        }
+    })
+}
+
+fn decode_etc2_rgb(block: &[u8]) -> Block {
+    decode_etc2_color(block, false)
+}
+
+fn decode_etc2_rgb_a1(block: &[u8]) -> Block {
+    decode_etc2_color(block, true)
+}
+
+fn decode_etc2_rgba(block: &[u8]) -> Block {
+    let alpha = decode_eac_channel(&block[..8], false, false);
+    let mut texels = decode_etc2_color(&block[8..], false);
+
+    for (texel, alpha) in texels.iter_mut().zip(alpha) {
+        texel[3] = alpha;
+    }
+
+    texels
+}
+
+fn decode_eac_r11(block: &[u8]) -> Block {
+    decode_eac_channel(block, true, false).map(|r| [r, 0, 0, 255])
+}
+
+fn decode_eac_r11_signed(block: &[u8]) -> Block {
+    decode_eac_channel(block, true, true).map(|r| [r, 0, 0, 127])
+}
+
+fn decode_eac_rg11(block: &[u8]) -> Block {
+    let red = decode_eac_channel(&block[..8], true, false);
+    let green = decode_eac_channel(&block[8..], true, false);
+
+    array::from_fn(|i| [red[i], green[i], 0, 255])
+}
+
+fn decode_eac_rg11_signed(block: &[u8]) -> Block {
+    let red = decode_eac_channel(&block[..8], true, true);
+    let green = decode_eac_channel(&block[8..], true, true);
+
+    array::from_fn(|i| [red[i], green[i], 0, 127])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::TextureFormat as F;
+
+    // Little endian block of 8 or 16 bytes with the given (offset, count, value) fields set
+    fn block(size: usize, fields: &[(u32, u32, u128)]) -> Vec<u8> {
+        let bits = fields.iter().fold(0u128, |bits, &(offset, count, value)| {
+            bits | (value & ((1 << count) - 1)) << offset
+        });
+        bits.to_le_bytes()[..size].to_vec()
+    }
+
+    fn decode(format: F, block: &[u8]) -> Vec<u8> {
+        let (width, height) = format.block_dimensions();
+        decompress(format, block, (width, height)).unwrap()
+    }
+
+    fn texels(data: &[u8]) -> Vec<[u8; 4]> {
+        data.chunks_exact(4)
+            .map(|texel| texel.try_into().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn bc1_blocks() {
+        // Red to blue, every texel on the first interpolated color
+        let four_color = block(
+            8,
+            &[(0, 16, 0xf800), (16, 16, 0x001f), (32, 32, 0xaaaaaaaa)],
+        );
+        assert_eq!(
+            texels(&decode(F::Bc1RgbaUnorm, &four_color)),
+            [[170, 0, 85, 255]; 16]
+        );
+
+        // Endpoints in the other order switch to three colors and transparent black
+        let three_color = block(
+            8,
+            &[(0, 16, 0x001f), (16, 16, 0xf800), (32, 32, 0xffffffff)],
+        );
+        assert_eq!(texels(&decode(F::Bc1RgbaUnorm, &three_color)), [[0; 4]; 16]);
+    }
+
+    #[test]
+    fn bc4_signed_block() {
+        // -128 acts like -127, the last two codes of the six value mode are -1 and 1
+        let data = block(
+            8,
+            &[
+                (0, 8, 0x80),
+                (8, 8, 0x7f),
+                (16, 3, 1),
+                (19, 3, 6),
+                (22, 3, 7),
+            ],
+        );
+        let red: Vec<i8> = texels(&decode(F::Bc4RSnorm, &data))
+            .iter()
+            .map(|texel| texel[0] as i8)
+            .collect();
+
+        assert_eq!(red[..4], [127, -127, 127, -127]);
+        assert_eq!(texels(&decode(F::Bc4RSnorm, &data))[0][1..], [0, 0, 127]);
+    }
+
+    #[test]
+    fn bc6h_blocks() {
+        // Mode 11, a single region with 10 bit endpoints that are equal
+        let endpoints = |r: u128, g: u128, b: u128| {
+            block(
+                16,
+                &[
+                    (0, 5, 0b00011),
+                    (5, 10, r),
+                    (15, 10, g),
+                    (25, 10, b),
+                    (35, 10, r),
+                    (45, 10, g),
+                    (55, 10, b),
+                ],
+            )
+        };
+        let half = |data: Vec<u8>| -> Vec<u16> {
+            data.chunks_exact(2)
+                .map(|half| u16::from_le_bytes([half[0], half[1]]))
+                .collect()
+        };
+
+        let data = half(decode(F::Bc6hRgbUfloat, &endpoints(0x200, 0, 0x3ff)));
+        assert_eq!(data[..4], [0x3e0f, 0, 0x7bff, 0x3c00]);
+
+        // Signed endpoints are sign extended, 0x200 is the most negative one
+        let data = half(decode(F::Bc6hRgbFloat, &endpoints(0x200, 0, 0x1ff)));
+        assert_eq!(data[..4], [0xfbff, 0, 0x7bff, 0x3c00]);
+
+        // Reserved modes decode to black
+        let data = half(decode(F::Bc6hRgbUfloat, &block(16, &[(0, 5, 0b10011)])));
+        assert_eq!(data[..4], [0, 0, 0, 0x3c00]);
+    }
+
+    #[test]
+    fn bc7_block() {
+        // Mode 6 with both endpoints at the same color and alpha
+        let data = block(
+            16,
+            &[
+                (0, 7, 1 << 6),
+                (7, 14, 0x40 | 0x40 << 7),
+                (21, 14, 0x20 | 0x20 << 7),
+                (35, 14, 0x10 | 0x10 << 7),
+                (49, 14, 0x7f | 0x7f << 7),
+                (63, 2, 0b11),
+            ],
+        );
+        assert_eq!(
+            texels(&decode(F::Bc7RgbaUnorm, &data)),
+            [[129, 65, 33, 255]; 16]
+        );
+    }
+
+    #[test]
+    fn etc2_and_eac_blocks() {
+        // Individual mode with the same base color for both halves and every texel offset
+        // by the small modifier of the first table
+        let base = (8u64 << 60 | 8 << 56 | 4 << 52 | 4 << 48).to_be_bytes();
+        assert_eq!(
+            texels(&decode(F::Etc2Rgb8Unorm, &base)),
+            [[138, 70, 2, 255]; 16]
+        );
+
+        // Signed eac with a base of -100 and every texel on the +2 modifier
+        let indices = (0..16).fold(0u64, |bits, i| bits | 4 << (i * 3));
+        let eac = (0x9cu64 << 56 | 1 << 52 | indices).to_be_bytes();
+        let red = texels(&decode(F::EacR11Snorm, &eac));
+        assert_eq!(red, [[-97i8 as u8, 0, 0, 127]; 16]);
+    }
+
+    #[test]
+    fn astc_blocks() {
+        let format = F::Astc {
+            block: wgpu::AstcBlock::B4x4,
+            channel: wgpu::AstcChannel::Unorm,
+        };
+
+        // Void extent covering the whole texture
+        let void_extent = block(
+            16,
+            &[
+                (0, 12, 0xdfc),
+                (12, 52, u128::MAX),
+                (64, 16, 0xffff),
+                (80, 16, 0x8000),
+                (96, 16, 0),
+                (112, 16, 0xffff),
+            ],
+        );
+        assert_eq!(
+            texels(&decode(format, &void_extent)),
+            [[255, 128, 0, 255]; 16]
+        );
+
+        // A 4x4 grid of two bit weights going 0 to 3 along each row, between black and white
+        // luminance endpoints stored with 8 bits each
+        let fields = block(16, &[(0, 11, 0x042), (17, 8, 0), (25, 8, 255)]);
+        let weights: u128 = (0..16).fold(0, |bits, i| bits | (i % 4) << (i * 2));
+        let data = u128::from_le_bytes(fields.try_into().unwrap()) | weights.reverse_bits();
+        let luminance: Vec<u8> = texels(&decode(format, &data.to_le_bytes()))
+            .iter()
+            .map(|texel| texel[0])
+            .collect();
+        assert_eq!(luminance, [0, 84, 171, 255].repeat(4));
+
+        // Reserved block modes decode to the error color
+        assert_eq!(texels(&decode(format, &[0; 16])), [[255, 0, 255, 255]; 16]);
+    }
+
+    #[test]
+    fn partial_blocks_and_errors() {
+        // Blocks hanging over the edge get cut off
+        let red = block(8, &[(0, 16, 0xf800), (16, 16, 0xf800)]);
+        let data = decompress(F::Bc1RgbaUnorm, &red.repeat(2), (5, 3)).unwrap();
+        assert_eq!(texels(&data), [[255, 0, 0, 255]; 15]);
+
+        assert!(matches!(
+            decompress(F::Bc1RgbaUnorm, &red, (5, 3)),
+            Err(RenderError::InvalidDataSize {
+                expected: 16,
+                actual: 8
+            })
+        ));
+
+        let hdr = F::Astc {
+            block: wgpu::AstcBlock::B4x4,
+            channel: wgpu::AstcChannel::Hdr,
+        };
+        assert_eq!(decompressed_format(hdr), None);
+        assert!(matches!(
+            decompress(hdr, &[0; 16], (4, 4)),
+            Err(RenderError::UnsupportedFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::tonemap::{TonemapOperator, Tonemapping};
+use crate::uniform::Uniform;
+
+pub static DISPLAY_SHADER: &str = include_str!("display.wgsl");
+
+// How display_tex fits a texture onto a surface of a different size
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ScaleMode {
+    // Covers the whole surface, ignoring the aspect ratio
+    #[default]
+    Stretch,
+    // Largest size that fits while keeping the aspect ratio, bars on two sides
+    Fit,
+    // Smallest size that covers the surface while keeping the aspect ratio, crops two sides
+    Fill,
+    // Largest whole multiple of the texture size that fits, for pixel art. Sampled with a
+    // nearest sampler, textures bigger than the surface are scaled down like Fit
+    Integer,
+}
+
+// Where the texture ends up on the surface, in surface pixels. Can extend past the surface
+// for ScaleMode::Fill
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DisplayRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl DisplayRect {
+    pub fn new(mode: ScaleMode, texture_size: (u32, u32), surface_size: (u32, u32)) -> Self {
+        let (tw, th) = (texture_size.0 as f32, texture_size.1 as f32);
+        let (sw, sh) = (surface_size.0 as f32, surface_size.1 as f32);
+
+        let scale = match mode {
+            ScaleMode::Stretch => {
+                return Self {
+                    x: 0.0,
+                    y: 0.0,
+                    width: sw,
+                    height: sh,
+                }
+            }
+            ScaleMode::Fit => (sw / tw).min(sh / th),
+            ScaleMode::Fill => (sw / tw).max(sh / th),
+            ScaleMode::Integer => {
+                let fit = (sw / tw).min(sh / th);
+                match fit >= 1.0 {
+                    true => fit.floor(),
+                    false => fit,
+                }
+            }
+        };
+
+        let (width, height) = (tw * scale, th * scale);
+
+        // Whole pixel offsets keep integer scaled texels aligned with the surface
+        Self {
+            x: ((sw - width) / 2.0).floor(),
+            y: ((sh - height) / 2.0).floor(),
+            width,
+            height,
+        }
+    }
+
+    // Part of the rect actually on the surface, used as the viewport
+    pub(crate) fn clipped(&self, surface_size: (u32, u32)) -> Self {
+        let x = self.x.max(0.0);
+        let y = self.y.max(0.0);
+
+        Self {
+            x,
+            y,
+            width: (self.x + self.width).min(surface_size.0 as f32) - x,
+            height: (self.y + self.height).min(surface_size.1 as f32) - y,
+        }
+    }
+
+    // Maps a position on the surface to a pixel position in the texture, None when the
+    // position lands on the bars
+    pub fn surface_to_texture(
+        &self,
+        position: (f32, f32),
+        texture_size: (u32, u32),
+    ) -> Option<(f32, f32)> {
+        let u = (position.0 - self.x) / self.width;
+        let v = (position.1 - self.y) / self.height;
+
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
+        }
+
+        Some((u * texture_size.0 as f32, v * texture_size.1 as f32))
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DisplayParams {
+    uv_offset: [f32; 2],
+    uv_scale: [f32; 2],
+    exposure: f32,
+    curve: u32,
+}
+
+pub(crate) fn display_uniform(
+    rect: &DisplayRect,
+    surface_size: (u32, u32),
+    tonemapping: &Tonemapping,
+    operator: TonemapOperator,
+) -> Uniform {
+    let clipped = rect.clipped(surface_size);
+
+    let params = DisplayParams {
+        uv_offset: [
+            (clipped.x - rect.x) / rect.width,
+            (clipped.y - rect.y) / rect.height,
+        ],
+        uv_scale: [clipped.width / rect.width, clipped.height / rect.height],
+        exposure: tonemapping.exposure,
+        curve: operator as u32,
+    };
+
+    Uniform {
+        data: bytemuck::bytes_of(&params).to_vec(),
+        stages: wgpu::ShaderStages::VERTEX_FRAGMENT,
+        dynamic: None,
+    }
+}
@@ -0,0 +1,132 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+};
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+};
+
+// Part of the texture shown in the viewport, everything is shown unless cropped by fill scaling
+struct DisplayParams {
+    uv_offset: vec2<f32>,
+    uv_scale: vec2<f32>,
+    exposure: f32,
+    // 0 none, 1 reinhard, 2 aces, 3 agx
+    curve: u32,
+};
+
+@group(0)
+@binding(0)
+var<storage, read> params: DisplayParams;
+
+@group(1)
+@binding(0)
+var fullscreen_tex_sampler: sampler;
+@group(1)
+@binding(1)
+var fullscreen_tex: texture_2d<f32>;
+
+@vertex
+fn vs(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    // Two triangles covering the viewport
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 1.0),
+    );
+    let corner = corners[index];
+
+    out.clip_position = vec4<f32>(corner.x * 2.0 - 1.0, 1.0 - corner.y * 2.0, 0.0, 1.0);
+    out.uv = params.uv_offset + corner * params.uv_scale;
+
+    return out;
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (vec3<f32>(1.0) + color);
+}
+
+// Krzysztof Narkowicz's fit of the aces filmic curve
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Polynomial fit of the default agx contrast curve
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+
+    return 15.5 * x4 * x2
+        - 40.14 * x4 * x
+        + 31.96 * x4
+        - 6.868 * x2 * x
+        + 0.4298 * x2
+        + 0.1191 * x
+        - 0.00232;
+}
+
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104,
+    );
+    let outset = mat3x3<f32>(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116,
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var x = inset * color;
+    x = clamp(log2(max(x, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    x = (x - min_ev) / (max_ev - min_ev);
+    x = agx_contrast(x);
+    x = outset * x;
+
+    // The curve outputs display encoded values, the srgb surface encodes again
+    return pow(max(x, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+@fragment
+fn fs(in: VertexOutput) -> FragmentOutput {
+    var out: FragmentOutput;
+
+    let sampled = textureSampleLevel(fullscreen_tex, fullscreen_tex_sampler, in.uv, 0.0);
+    let color = sampled.rgb * params.exposure;
+    // Negative values only make sense on extended range surfaces which skip the operators
+    let positive = max(color, vec3<f32>(0.0));
+
+    var mapped: vec3<f32>;
+    switch params.curve {
+        case 1u: {
+            mapped = reinhard(positive);
+        }
+        case 2u: {
+            mapped = aces(positive);
+        }
+        case 3u: {
+            mapped = agx(positive);
+        }
+        default: {
+            mapped = color;
+        }
+    }
+
+    out.color = vec4<f32>(mapped, sampled.a);
+
+    return out;
+}
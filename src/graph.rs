@@ -0,0 +1,511 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use crate::mesh::PackedMesh;
+use crate::pass::{DrawKind, FrameEncoder};
+use crate::renderer::{DepthTextureInfo, RenderError, RenderingContext, TextureHandle};
+use crate::shader::Shader;
+use crate::texture::Texture;
+use crate::uniform::Uniform;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TargetSize {
+    // Fraction of the current surface size, 1.0 being the same size as the surface
+    SurfaceRelative(f32),
+    Fixed(u32, u32),
+}
+
+impl TargetSize {
+    pub fn resolve(&self, surface_size: (u32, u32)) -> (u32, u32) {
+        match *self {
+            TargetSize::SurfaceRelative(scale) => (
+                ((surface_size.0 as f32 * scale) as u32).max(1),
+                ((surface_size.1 as f32 * scale) as u32).max(1),
+            ),
+            TargetSize::Fixed(width, height) => (width, height),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TransientDesc {
+    pub size: TargetSize,
+    pub format: wgpu::TextureFormat,
+}
+
+// A render target owned by the context which the graph hands out to transient resources,
+// the same texture can back multiple transients within a frame if their lifetimes dont overlap
+pub struct TransientTexture {
+    pub handle: TextureHandle,
+    pub format: wgpu::TextureFormat,
+    pub dimensions: (u32, u32),
+}
+
+#[derive(Debug)]
+pub enum GraphError {
+    UnknownResource(String),
+    Cycle,
+    Render(RenderError),
+}
+
+impl std::fmt::Display for GraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphError::UnknownResource(name) => write!(f, "unknown graph resource {name:?}"),
+            GraphError::Cycle => write!(f, "render graph contains a cycle"),
+            GraphError::Render(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+impl From<RenderError> for GraphError {
+    fn from(err: RenderError) -> Self {
+        GraphError::Render(err)
+    }
+}
+
+enum GraphResource {
+    Imported(TextureHandle),
+    Transient(TransientDesc),
+}
+
+struct GraphDraw<'d> {
+    mesh: &'d PackedMesh,
+    shader: Shader,
+    uniforms: Vec<&'d Uniform>,
+    textures: Vec<String>,
+}
+
+pub struct GraphPass<'d> {
+    name: String,
+    reads: Vec<String>,
+    writes: Vec<String>,
+    depth: Option<(String, bool)>,
+    clear: Option<wgpu::Color>,
+    draws: Vec<GraphDraw<'d>>,
+}
+
+impl<'d> GraphPass<'d> {
+    pub fn read(&mut self, name: &str) -> &mut Self {
+        if !self.reads.iter().any(|read| read == name) {
+            self.reads.push(name.to_owned());
+        }
+
+        self
+    }
+
+    pub fn write(&mut self, name: &str) -> &mut Self {
+        self.writes.push(name.to_owned());
+
+        self
+    }
+
+    pub fn depth(&mut self, name: &str, clear_depth: bool) -> &mut Self {
+        self.depth = Some((name.to_owned(), clear_depth));
+
+        self
+    }
+
+    pub fn clear(&mut self, color: wgpu::Color) -> &mut Self {
+        self.clear = Some(color);
+
+        self
+    }
+
+    // Textures are referenced by their graph name and are implicitly read by the pass
+    pub fn draw(
+        &mut self,
+        mesh: &'d PackedMesh,
+        shader: &Shader,
+        uniforms: &[&'d Uniform],
+        textures: &[&str],
+    ) -> &mut Self {
+        for texture in textures {
+            self.read(texture);
+        }
+
+        self.draws.push(GraphDraw {
+            mesh,
+            shader: shader.clone(),
+            uniforms: uniforms.to_vec(),
+            textures: textures.iter().map(|name| name.to_string()).collect(),
+        });
+
+        self
+    }
+
+    fn written(&self) -> impl Iterator<Item = &String> {
+        self.writes
+            .iter()
+            .chain(self.depth.iter().map(|(name, _)| name))
+    }
+
+    fn used(&self) -> impl Iterator<Item = &String> {
+        self.reads.iter().chain(self.written())
+    }
+}
+
+// Passes declare which textures they read and write by name, on execute the graph orders
+// them by their dependencies, drops passes which dont contribute to an output and records
+// everything that is left into one encoder.
+//
+// Passes writing to imported textures are always kept since their results are visible
+// outside of the graph. Transient textures are only guaranteed to hold what was written
+// to them earlier in the same frame, so the first pass writing one should clear it
+pub struct RenderGraph<'d> {
+    resources: HashMap<String, GraphResource>,
+    outputs: Vec<String>,
+    passes: Vec<GraphPass<'d>>,
+    resolved: HashMap<String, TextureHandle>,
+}
+
+impl<'d> Default for RenderGraph<'d> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'d> RenderGraph<'d> {
+    pub fn new() -> Self {
+        Self {
+            resources: HashMap::new(),
+            outputs: Vec::new(),
+            passes: Vec::new(),
+            resolved: HashMap::new(),
+        }
+    }
+
+    pub fn import(&mut self, name: &str, texture: TextureHandle) -> &mut Self {
+        self.resources
+            .insert(name.to_owned(), GraphResource::Imported(texture));
+
+        self
+    }
+
+    pub fn transient(&mut self, name: &str, desc: TransientDesc) -> &mut Self {
+        self.resources
+            .insert(name.to_owned(), GraphResource::Transient(desc));
+
+        self
+    }
+
+    // Marks a resource as needed after the graph runs so the passes producing it arent culled
+    pub fn output(&mut self, name: &str) -> &mut Self {
+        self.outputs.push(name.to_owned());
+
+        self
+    }
+
+    pub fn add_pass(&mut self, name: &str) -> &mut GraphPass<'d> {
+        self.passes.push(GraphPass {
+            name: name.to_owned(),
+            reads: Vec::new(),
+            writes: Vec::new(),
+            depth: None,
+            clear: None,
+            draws: Vec::new(),
+        });
+
+        self.passes.last_mut().unwrap()
+    }
+
+    // Texture backing the given resource during the last execute
+    pub fn texture(&self, name: &str) -> Option<TextureHandle> {
+        self.resolved.get(name).copied()
+    }
+
+    pub fn execute(&mut self, ctx: &mut RenderingContext) -> Result<(), GraphError> {
+        for name in self
+            .passes
+            .iter()
+            .flat_map(|pass| pass.used())
+            .chain(self.outputs.iter())
+        {
+            if !self.resources.contains_key(name) {
+                return Err(GraphError::UnknownResource(name.clone()));
+            }
+        }
+
+        let dependencies = self.dependencies();
+        let alive = self.alive_passes(&dependencies);
+        let order = Self::sort_passes(&self.ordering(&dependencies), &alive)?;
+
+        self.resolved = self.allocate_textures(ctx, &order);
+
+        let mut frame = FrameEncoder::new();
+
+        for pass in order.iter().map(|idx| &self.passes[*idx]) {
+            let targets = pass
+                .writes
+                .iter()
+                .map(|name| self.resolved[name])
+                .collect::<Vec<_>>();
+
+            let depth = pass
+                .depth
+                .as_ref()
+                .map(|(name, clear_depth)| DepthTextureInfo {
+                    depth_texture: self.resolved[name],
+                    clear_depth: *clear_depth,
+                });
+
+            let pass_idx = frame.begin_pass(&pass.name, &targets, depth, pass.clear);
+
+            for draw in pass.draws.iter() {
+                let textures = draw
+                    .textures
+                    .iter()
+                    .map(|name| self.resolved[name].into())
+                    .collect::<Vec<_>>();
+
+                frame.draw(
+                    pass_idx,
+                    draw.mesh,
+                    &draw.shader,
+                    &draw.uniforms,
+                    &textures,
+                    DrawKind::Direct,
+                );
+            }
+        }
+
+        frame.submit(ctx)?;
+
+        Ok(())
+    }
+
+    // For every pass the passes whose results it uses. A read sees the most recent write
+    // declared before it, reads without one get what the texture held before the graph ran
+    fn dependencies(&self) -> Vec<Vec<usize>> {
+        self.passes
+            .iter()
+            .enumerate()
+            .map(|(idx, pass)| {
+                let mut deps = Vec::new();
+
+                for read in pass.reads.iter() {
+                    let writer = (0..idx)
+                        .rev()
+                        .find(|other| self.passes[*other].written().any(|name| name == read));
+
+                    if let Some(writer) = writer {
+                        deps.push(writer);
+                    }
+                }
+
+                // Writing to the same texture keeps the declaration order
+                for (other_idx, other) in self.passes[..idx].iter().enumerate() {
+                    if pass
+                        .written()
+                        .any(|written| other.written().any(|other| other == written))
+                    {
+                        deps.push(other_idx);
+                    }
+                }
+
+                deps.into_iter().unique().collect()
+            })
+            .collect()
+    }
+
+    // Dependencies plus passes which have to read a texture before this one overwrites it.
+    // Those dont keep the reader alive, so they only matter for the execution order
+    fn ordering(&self, dependencies: &[Vec<usize>]) -> Vec<Vec<usize>> {
+        self.passes
+            .iter()
+            .enumerate()
+            .map(|(idx, pass)| {
+                let readers = self.passes[..idx]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, other)| {
+                        other
+                            .reads
+                            .iter()
+                            .any(|read| pass.written().any(|written| written == read))
+                    })
+                    .map(|(other_idx, _)| other_idx);
+
+                dependencies[idx]
+                    .iter()
+                    .copied()
+                    .chain(readers)
+                    .unique()
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn alive_passes(&self, dependencies: &[Vec<usize>]) -> Vec<bool> {
+        let mut alive = self
+            .passes
+            .iter()
+            .map(|pass| {
+                pass.written().any(|name| {
+                    self.outputs.contains(name)
+                        || matches!(self.resources.get(name), Some(GraphResource::Imported(_)))
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut stack = (0..alive.len())
+            .filter(|idx| alive[*idx])
+            .collect::<Vec<_>>();
+
+        while let Some(idx) = stack.pop() {
+            for dep in dependencies[idx].iter() {
+                if !alive[*dep] {
+                    alive[*dep] = true;
+                    stack.push(*dep);
+                }
+            }
+        }
+
+        alive
+    }
+
+    // Kahn's algorithm, ties are broken by declaration order so independent passes
+    // run in the order they were added
+    fn sort_passes(dependencies: &[Vec<usize>], alive: &[bool]) -> Result<Vec<usize>, GraphError> {
+        let mut remaining = dependencies
+            .iter()
+            .map(|deps| deps.iter().filter(|dep| alive[**dep]).count())
+            .collect::<Vec<_>>();
+
+        let mut order = Vec::new();
+        let mut done = vec![false; dependencies.len()];
+
+        while let Some(next) =
+            (0..dependencies.len()).find(|idx| alive[*idx] && !done[*idx] && remaining[*idx] == 0)
+        {
+            done[next] = true;
+            order.push(next);
+
+            for (idx, deps) in dependencies.iter().enumerate() {
+                if deps.contains(&next) {
+                    remaining[idx] -= 1;
+                }
+            }
+        }
+
+        if order.len() != alive.iter().filter(|alive| **alive).count() {
+            return Err(GraphError::Cycle);
+        }
+
+        Ok(order)
+    }
+
+    fn allocate_textures(
+        &self,
+        ctx: &mut RenderingContext,
+        order: &[usize],
+    ) -> HashMap<String, TextureHandle> {
+        let mut resolved = HashMap::new();
+
+        // First and last position in the execution order where each transient is used
+        let mut lifetimes: HashMap<&String, (usize, usize)> = HashMap::new();
+
+        for (position, pass) in order.iter().map(|idx| &self.passes[*idx]).enumerate() {
+            for name in pass.used() {
+                match self.resources.get(name) {
+                    Some(GraphResource::Imported(handle)) => {
+                        resolved.insert(name.clone(), *handle);
+                    }
+                    Some(GraphResource::Transient(_)) => {
+                        let lifetime = lifetimes.entry(name).or_insert((position, position));
+                        lifetime.1 = position;
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        // Outputs have to survive until the end of the frame
+        for name in self.outputs.iter() {
+            if let Some(GraphResource::Imported(handle)) = self.resources.get(name) {
+                resolved.insert(name.clone(), *handle);
+            }
+            if let Some(lifetime) = lifetimes.get_mut(name) {
+                lifetime.1 = usize::MAX;
+            }
+        }
+
+        let surface_size = ctx.surface_size();
+
+        // Position after which each pooled texture is free again during this frame
+        let mut busy_until: Vec<Option<usize>> = vec![None; ctx.transient_textures.len()];
+
+        let sorted_lifetimes = lifetimes
+            .into_iter()
+            .sorted_by_key(|(_, (first, _))| *first)
+            .collect::<Vec<_>>();
+
+        for (name, (first, last)) in sorted_lifetimes {
+            let Some(GraphResource::Transient(desc)) = self.resources.get(name) else {
+                continue;
+            };
+
+            let dimensions = desc.size.resolve(surface_size);
+
+            let free = |idx: &usize| busy_until[*idx].is_none_or(|until| until < first);
+
+            let matching = (0..ctx.transient_textures.len()).find(|idx| {
+                let pooled = &ctx.transient_textures[*idx];
+                pooled.format == desc.format && pooled.dimensions == dimensions && free(idx)
+            });
+
+            // A texture of the right format that nothing used this frame can be resized
+            // instead of allocating another one
+            let resizable = || {
+                (0..ctx.transient_textures.len()).find(|idx| {
+                    ctx.transient_textures[*idx].format == desc.format && busy_until[*idx].is_none()
+                })
+            };
+
+            let pool_idx = match matching.or_else(resizable) {
+                Some(idx) => {
+                    let pooled = &mut ctx.transient_textures[idx];
+                    if pooled.dimensions != dimensions {
+                        pooled.dimensions = dimensions;
+                        if let Some(texture) = ctx.textures.get_mut(pooled.handle) {
+                            texture.resize(&ctx.device, dimensions);
+                        }
+                    }
+
+                    idx
+                }
+                None => {
+                    let texture = Texture::new(
+                        &ctx.device,
+                        &ctx.queue,
+                        &[],
+                        dimensions,
+                        desc.format,
+                        wgpu::TextureUsages::TEXTURE_BINDING
+                            | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                        wgpu::FilterMode::Linear,
+                        1,
+                    );
+                    let handle = ctx.textures.insert(texture);
+
+                    ctx.transient_textures.push(TransientTexture {
+                        handle,
+                        format: desc.format,
+                        dimensions,
+                    });
+                    busy_until.push(None);
+
+                    ctx.transient_textures.len() - 1
+                }
+            };
+
+            busy_until[pool_idx] = Some(last);
+            resolved.insert(name.clone(), ctx.transient_textures[pool_idx].handle);
+        }
+
+        resolved
+    }
+}
@@ -1,10 +1,35 @@
+#[cfg(feature = "compressed")]
+mod astc;
 #[macro_use]
 pub mod camera;
+#[cfg(feature = "image")]
+pub mod capture;
+pub mod compute;
+#[cfg(feature = "compressed")]
+pub mod container;
+pub mod context;
+#[cfg(feature = "compressed")]
+pub mod decompress;
+pub mod display;
+pub mod graph;
+#[cfg(feature = "image")]
+pub mod loader;
 pub mod mesh;
+pub mod mipmap;
+pub mod occlusion;
+pub mod pass;
+pub mod profiler;
+mod readback;
 pub mod renderer;
+pub mod resources;
+pub mod sampler;
 pub mod shader;
+pub mod slotmap;
+pub mod surface;
 pub mod texture;
+pub mod tonemap;
 pub mod uniform;
+mod upload;
 pub mod vertex;
 
 //Reexports
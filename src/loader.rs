@@ -0,0 +1,227 @@
+use std::io::Cursor;
+use std::path::Path;
+use std::time::Instant;
+
+use image::{DynamicImage, ImageReader};
+
+use crate::renderer::{RenderError, RenderingContext, TextureHandle};
+use crate::texture::{full_mip_count, Texture, TextureDesc, TextureKind};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    // Srgb for 8 and 16 bit images, linear for float (hdr) images
+    #[default]
+    Auto,
+    Srgb,
+    // Data textures like normal or roughness maps
+    Linear,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ImageOptions {
+    // Float images are always linear, this only picks the format of the others
+    pub color_space: ColorSpace,
+    pub premultiply_alpha: bool,
+    pub mipmaps: bool,
+    pub sampler_type: wgpu::FilterMode,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            color_space: ColorSpace::Auto,
+            premultiply_alpha: false,
+            mipmaps: false,
+            sampler_type: wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Image(image::ImageError),
+    Render(RenderError),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Image(err) => write!(f, "{err}"),
+            LoadError::Render(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<image::ImageError> for LoadError {
+    fn from(err: image::ImageError) -> Self {
+        LoadError::Image(err)
+    }
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(err: std::io::Error) -> Self {
+        LoadError::Image(image::ImageError::IoError(err))
+    }
+}
+
+impl From<RenderError> for LoadError {
+    fn from(err: RenderError) -> Self {
+        LoadError::Render(err)
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    match value <= 0.04045 {
+        true => value / 12.92,
+        false => ((value + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    match value <= 0.0031308 {
+        true => value * 12.92,
+        false => 1.055 * value.powf(1.0 / 2.4) - 0.055,
+    }
+}
+
+// Srgb colors are multiplied in linear space, the same thing the gpu does when blending
+fn premultiply_rgba8(pixels: &mut [u8], srgb: bool) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3] as f32 / 255.0;
+
+        for channel in pixel.iter_mut().take(3) {
+            let value = *channel as f32 / 255.0;
+            let value = match srgb {
+                true => linear_to_srgb(srgb_to_linear(value) * alpha),
+                false => value * alpha,
+            };
+            *channel = (value * 255.0).round() as u8;
+        }
+    }
+}
+
+// Texel data and format a decoded image gets uploaded as
+fn texture_data(
+    image: DynamicImage,
+    options: &ImageOptions,
+) -> (Vec<u8>, (u32, u32), wgpu::TextureFormat) {
+    let dimensions = (image.width(), image.height());
+
+    let float = matches!(
+        image,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+
+    if float {
+        let mut pixels = image.into_rgba32f().into_raw();
+
+        if options.premultiply_alpha {
+            for pixel in pixels.chunks_exact_mut(4) {
+                let alpha = pixel[3];
+                pixel
+                    .iter_mut()
+                    .take(3)
+                    .for_each(|channel| *channel *= alpha);
+            }
+        }
+
+        // Rgba32Float can't be filtered on most devices, half precision is plenty for color
+        let pixels = pixels
+            .into_iter()
+            .map(half::f16::from_f32)
+            .collect::<Vec<_>>();
+
+        return (
+            bytemuck::cast_slice(&pixels).to_vec(),
+            dimensions,
+            wgpu::TextureFormat::Rgba16Float,
+        );
+    }
+
+    let srgb = options.color_space != ColorSpace::Linear;
+    let mut pixels = image.into_rgba8().into_raw();
+
+    if options.premultiply_alpha {
+        premultiply_rgba8(&mut pixels, srgb);
+    }
+
+    let format = match srgb {
+        true => wgpu::TextureFormat::Rgba8UnormSrgb,
+        false => wgpu::TextureFormat::Rgba8Unorm,
+    };
+
+    (pixels, dimensions, format)
+}
+
+impl<'a> RenderingContext<'a> {
+    // Decodes png, jpeg, tga, bmp and radiance hdr files, the format is guessed from the
+    // contents rather than the extension
+    pub fn create_texture_from_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        options: ImageOptions,
+    ) -> Result<TextureHandle, LoadError> {
+        let image = ImageReader::open(path)?.with_guessed_format()?.decode()?;
+
+        self.create_texture_from_image(image, options)
+    }
+
+    pub fn create_texture_from_bytes(
+        &mut self,
+        bytes: &[u8],
+        options: ImageOptions,
+    ) -> Result<TextureHandle, LoadError> {
+        let image = ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()?
+            .decode()?;
+
+        self.create_texture_from_image(image, options)
+    }
+
+    pub fn create_texture_from_image(
+        &mut self,
+        image: DynamicImage,
+        options: ImageOptions,
+    ) -> Result<TextureHandle, LoadError> {
+        let start = Instant::now();
+
+        let (data, dimensions, format) = texture_data(image, &options);
+
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC;
+        let mut mip_level_count = 1;
+
+        if options.mipmaps {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+            mip_level_count = full_mip_count(dimensions);
+        }
+
+        let texture = Texture::from_data(
+            &self.device,
+            &self.queue,
+            self.format_features(format),
+            &data,
+            TextureDesc {
+                dimensions,
+                kind: TextureKind::D2,
+                format,
+                usage,
+                sampler_type: options.sampler_type,
+                mip_level_count,
+            },
+        )?;
+
+        self.record_cpu_time("texture upload", start);
+
+        let handle = self.textures.insert(texture);
+        if let Err(err) = self.generate_mipmaps(handle) {
+            self.destroy_texture(handle)?;
+            return Err(err.into());
+        }
+
+        Ok(handle)
+    }
+}
@@ -1,9 +1,6 @@
-use std::marker::PhantomData;
-
-use encase::UniformBuffer;
-use glm::Vec3;
-use wgpu::VertexFormat;
+use wgpu::util::DrawIndexedIndirectArgs;
 
+use crate::renderer::RenderError;
 use crate::vertex::Vertex;
 
 use nalgebra_glm as glm;
@@ -17,7 +14,7 @@ pub struct VertexLayoutInfo {
 }
 
 impl VertexLayoutInfo {
-    pub fn descriptor(&self) -> wgpu::VertexBufferLayout {
+    pub fn descriptor(&self) -> wgpu::VertexBufferLayout<'_> {
         wgpu::VertexBufferLayout {
             array_stride: self.array_stride,
             step_mode: self.step_mode,
@@ -64,6 +61,48 @@ pub struct Mesh<V> {
 }
 
 impl Mesh<u8> {
+    // Packs meshes sharing a layout into one mesh and describes where each of them ended up,
+    // meant to be uploaded into a storage buffer and drawn (or culled) indirectly
+    pub fn pack_indirect(
+        meshes: &[PackedMesh],
+    ) -> Result<(PackedMesh, Vec<DrawIndexedIndirectArgs>), RenderError> {
+        let Some(first) = meshes.first() else {
+            return Err(RenderError::InvalidIndirectPack);
+        };
+        if meshes.iter().any(|mesh| mesh.layout != first.layout) {
+            return Err(RenderError::InvalidIndirectPack);
+        }
+
+        let layout = first.layout.clone();
+        let mut packed = PackedMesh {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            layout: layout.clone(),
+            could_be_transparent: false,
+        };
+
+        let args = meshes
+            .iter()
+            .map(|mesh| {
+                let args = DrawIndexedIndirectArgs {
+                    index_count: mesh.indices.len() as u32,
+                    instance_count: 1,
+                    first_index: packed.indices.len() as u32,
+                    base_vertex: (packed.vertices.len() as u64 / layout.total_size.max(1)) as i32,
+                    first_instance: 0,
+                };
+
+                packed.vertices.extend_from_slice(&mesh.vertices);
+                packed.indices.extend_from_slice(&mesh.indices);
+                packed.could_be_transparent |= mesh.could_be_transparent;
+
+                args
+            })
+            .collect();
+
+        Ok((packed, args))
+    }
+
     pub fn merge(&mut self, other: &mut Mesh<u8>) {
         assert!(self.layout == other.layout);
 
@@ -0,0 +1,31 @@
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+// One triangle covering the whole target, no vertex buffer needed
+@vertex
+fn vs(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+// Averages the 2x2 block of the previous level. Loads from srgb textures are already
+// linear and the write converts back, so the filtering happens in linear space
+@fragment
+fn fs(in: VertexOutput) -> @location(0) vec4<f32> {
+    let max_coord = vec2<i32>(textureDimensions(source)) - 1;
+    let base = vec2<i32>(in.position.xy) * 2;
+
+    let a = textureLoad(source, min(base, max_coord), 0);
+    let b = textureLoad(source, min(base + vec2<i32>(1, 0), max_coord), 0);
+    let c = textureLoad(source, min(base + vec2<i32>(0, 1), max_coord), 0);
+    let d = textureLoad(source, min(base + vec2<i32>(1, 1), max_coord), 0);
+
+    return (a + b + c + d) * 0.25;
+}
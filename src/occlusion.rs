@@ -0,0 +1,50 @@
+use crate::readback::QueryReadback;
+
+pub type OcclusionQueryHandle = usize;
+
+// How many frames can wait for their results to be read back at once
+const FRAMES_IN_FLIGHT: usize = 3;
+
+// A set of occlusion queries, every query counts the samples which passed the depth test
+// for the draws wrapped in it. Results arrive a frame or two after resolve is called
+pub struct OcclusionQuerySet {
+    pub query_set: wgpu::QuerySet,
+    pub count: u32,
+    readback: QueryReadback<()>,
+    results: Vec<u64>,
+}
+
+impl OcclusionQuerySet {
+    pub fn new(device: &wgpu::Device, count: u32) -> Self {
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("occlusion queries"),
+            ty: wgpu::QueryType::Occlusion,
+            count,
+        });
+
+        crate::debug!("Created {} occlusion queries", count);
+
+        Self {
+            query_set,
+            count,
+            readback: QueryReadback::new(device, count, FRAMES_IN_FLIGHT),
+            results: Vec::new(),
+        }
+    }
+
+    // Should be called once all passes using the queries this frame were submitted
+    pub fn resolve(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.readback
+            .resolve(device, queue, &self.query_set, self.count, ());
+    }
+
+    // Visible sample counts from the newest frame that finished reading back,
+    // empty until the first one does
+    pub fn results(&mut self, device: &wgpu::Device) -> &[u64] {
+        if let Some((results, _)) = self.readback.collect(device) {
+            self.results = results;
+        }
+
+        &self.results
+    }
+}
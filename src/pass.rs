@@ -0,0 +1,681 @@
+use std::time::Instant;
+
+use itertools::Itertools;
+use wgpu::util::DrawIndexedIndirectArgs;
+
+use crate::compute::BufferHandle;
+use crate::mesh::PackedMesh;
+use crate::occlusion::OcclusionQueryHandle;
+use crate::renderer::{
+    DepthTextureInfo, RenderError, RenderPipelineInfo, RenderingContext, TextureHandle,
+};
+use crate::sampler::TextureBinding;
+use crate::shader::Shader;
+use crate::uniform::Uniform;
+
+// Draw arguments read from a storage buffer (usually written by a compute pass) instead of
+// the mesh, `count` consecutive DrawIndexedIndirectArgs are read starting at `offset`.
+// Indices and vertices in the args are relative to the start of the drawn mesh
+#[derive(Copy, Clone, Debug)]
+pub struct IndirectArgs {
+    pub buffer: BufferHandle,
+    pub offset: u64,
+    pub count: u32,
+}
+
+impl IndirectArgs {
+    pub fn new(buffer: BufferHandle) -> Self {
+        Self {
+            buffer,
+            offset: 0,
+            count: 1,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum DrawKind {
+    Direct,
+    Indirect(IndirectArgs),
+    // Only depth tested, doesnt write color or depth. Meant for drawing bounding volumes
+    // inside of occlusion queries
+    OcclusionTest,
+}
+
+struct DrawCall<'d> {
+    mesh: &'d PackedMesh,
+    shader: Shader,
+    // Indices into FrameEncoder::uniforms
+    uniforms: Vec<usize>,
+    textures: Vec<TextureBinding>,
+    kind: DrawKind,
+    occlusion_query: Option<u32>,
+}
+
+// Where a single draw call ended up inside of the shared vertex/index buffers
+struct DrawRange {
+    vertex_offset: u64,
+    vertex_size: u64,
+    first_index: u32,
+    index_count: u32,
+}
+
+struct PassRecord<'d> {
+    label: String,
+    targets: Vec<TextureHandle>,
+    depth: Option<DepthTextureInfo>,
+    clear: Option<wgpu::Color>,
+    draws: Vec<DrawCall<'d>>,
+    occlusion_queries: Option<OcclusionQueryHandle>,
+    // Query the draws recorded right now end up in
+    current_query: Option<u32>,
+    // Queries begun in this pass, each one can only be used once per pass
+    begun_queries: Vec<u32>,
+    // First invalid query use, returned from submit
+    error: Option<RenderError>,
+}
+
+// Collects the draws of any number of passes and records all of them into a single
+// encoder and submit. Geometry of every draw is uploaded once into the shared buffers
+// and uniforms get one binding for the whole frame
+pub(crate) struct FrameEncoder<'d> {
+    // Distinct uniforms used by the draws, the same uniform used in multiple draws
+    // gets only one binding
+    uniforms: Vec<&'d Uniform>,
+    passes: Vec<PassRecord<'d>>,
+}
+
+impl<'d> FrameEncoder<'d> {
+    pub fn new() -> Self {
+        Self {
+            uniforms: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+
+    pub fn begin_pass(
+        &mut self,
+        label: &str,
+        targets: &[TextureHandle],
+        depth: Option<DepthTextureInfo>,
+        clear: Option<wgpu::Color>,
+    ) -> usize {
+        self.passes.push(PassRecord {
+            label: label.to_owned(),
+            targets: targets.to_vec(),
+            depth,
+            clear,
+            draws: Vec::new(),
+            occlusion_queries: None,
+            current_query: None,
+            begun_queries: Vec::new(),
+            error: None,
+        });
+
+        self.passes.len() - 1
+    }
+
+    pub fn set_occlusion_queries(&mut self, pass: usize, queries: OcclusionQueryHandle) {
+        self.passes[pass].occlusion_queries = Some(queries);
+    }
+
+    pub fn begin_occlusion_query(&mut self, pass: usize, query: u32) {
+        let pass = &mut self.passes[pass];
+
+        let error = if pass.occlusion_queries.is_none() {
+            Some(RenderError::MissingOcclusionQueries)
+        } else if pass.begun_queries.contains(&query) {
+            Some(RenderError::InvalidOcclusionQuery(query))
+        } else {
+            None
+        };
+
+        if let Some(error) = error {
+            pass.error.get_or_insert(error);
+            return;
+        }
+
+        pass.begun_queries.push(query);
+        pass.current_query = Some(query);
+    }
+
+    pub fn end_occlusion_query(&mut self, pass: usize) {
+        self.passes[pass].current_query = None;
+    }
+
+    pub fn draw(
+        &mut self,
+        pass: usize,
+        mesh: &'d PackedMesh,
+        shader: &Shader,
+        uniforms: &[&'d Uniform],
+        textures: &[TextureBinding],
+        kind: DrawKind,
+    ) {
+        let uniforms = uniforms
+            .iter()
+            .map(|uniform| {
+                match self
+                    .uniforms
+                    .iter()
+                    .position(|existing| std::ptr::eq(*existing, *uniform))
+                {
+                    Some(idx) => idx,
+                    None => {
+                        self.uniforms.push(uniform);
+                        self.uniforms.len() - 1
+                    }
+                }
+            })
+            .collect();
+
+        let pass = &mut self.passes[pass];
+        pass.draws.push(DrawCall {
+            mesh,
+            shader: shader.clone(),
+            uniforms,
+            textures: textures.to_vec(),
+            kind,
+            occlusion_query: pass.current_query,
+        });
+    }
+
+    pub fn submit(self, ctx: &mut RenderingContext) -> Result<(), RenderError> {
+        let FrameEncoder {
+            uniforms,
+            mut passes,
+        } = self;
+
+        ctx.flush_texture_uploads();
+
+        // Nothing gets recorded if any handle went stale, a destroyed slot could already
+        // hold an unrelated texture
+        for pass in passes.iter_mut() {
+            if let Some(err) = pass.error.take() {
+                return Err(err);
+            }
+
+            if let Some(queries) = pass.occlusion_queries {
+                let count = ctx
+                    .occlusion_queries
+                    .get(queries)
+                    .ok_or(RenderError::StaleOcclusionQueries(queries))?
+                    .count;
+
+                if let Some(query) = pass.begun_queries.iter().find(|query| **query >= count) {
+                    return Err(RenderError::InvalidOcclusionQuery(*query));
+                }
+            }
+
+            for draw in pass.draws.iter() {
+                if let DrawKind::Indirect(args) = draw.kind {
+                    let buffer = ctx
+                        .storage_buffers
+                        .get(args.buffer)
+                        .ok_or(RenderError::StaleBuffer(args.buffer))?;
+
+                    let end = (args.count as u64)
+                        .checked_mul(std::mem::size_of::<DrawIndexedIndirectArgs>() as u64)
+                        .and_then(|size| size.checked_add(args.offset));
+
+                    if args.offset % 4 != 0 || end.is_none_or(|end| end > buffer.size) {
+                        return Err(RenderError::InvalidIndirectArgs(args));
+                    }
+                }
+            }
+
+            let handles = pass
+                .targets
+                .iter()
+                .chain(pass.depth.iter().map(|depth| &depth.depth_texture));
+
+            for handle in handles.clone() {
+                if !ctx.textures.contains(*handle) {
+                    return Err(RenderError::StaleTexture(*handle));
+                }
+            }
+
+            // Textures written through storage bindings can't be used any other way within
+            // the same pass, a following pass can sample them
+            let bindings = pass.draws.iter().flat_map(|draw| draw.textures.iter());
+            let written = bindings.clone().filter(|binding| {
+                binding
+                    .storage
+                    .is_some_and(|access| access != wgpu::StorageTextureAccess::ReadOnly)
+            });
+
+            for binding in written {
+                let conflicting = handles.clone().any(|handle| *handle == binding.texture)
+                    || bindings.clone().any(|other| {
+                        other.texture == binding.texture && other.storage != binding.storage
+                    });
+
+                if conflicting {
+                    return Err(RenderError::ConflictingUsage(binding.texture));
+                }
+            }
+        }
+
+        let upload_start = Instant::now();
+
+        let binding_ids = ctx
+            .find_or_create_uniform_bindings(&uniforms)
+            .into_iter()
+            .sorted_by(|(original_idx_a, _), (original_idx_b, _)| {
+                original_idx_a.cmp(original_idx_b)
+            })
+            .map(|(_, id)| id)
+            .collect::<Vec<_>>();
+
+        for (uniform, binding_id) in uniforms.iter().zip(binding_ids.iter()) {
+            let binding = ctx.uniform_bindings.get(*binding_id).unwrap();
+            binding.update(&ctx.queue, &uniform.data);
+        }
+
+        ctx.record_cpu_time("upload", upload_start);
+
+        let pipeline_infos = passes
+            .iter()
+            .map(|pass| {
+                let output_formats = pass
+                    .targets
+                    .iter()
+                    .map(|target| {
+                        ctx.textures
+                            .get(*target)
+                            .expect("output texture does not exist")
+                            .format
+                    })
+                    .collect::<Vec<_>>();
+
+                pass.draws
+                    .iter()
+                    .map(|draw| {
+                        let texture_layouts = ctx.prepare_texture_bindings(&draw.textures)?;
+
+                        let pipeline_info = RenderPipelineInfo {
+                            vertex_layout: draw.mesh.layout.clone(),
+                            shader: draw.shader.clone(),
+                            textures: draw.textures.clone(),
+                            texture_layouts,
+                            depth: pass.depth.is_some(),
+                            uniform_binding_ids: draw
+                                .uniforms
+                                .iter()
+                                .map(|idx| binding_ids[*idx])
+                                .collect(),
+                            output_formats: output_formats.clone(),
+                            test_only: matches!(draw.kind, DrawKind::OcclusionTest),
+                        };
+
+                        ctx.create_pipeline_if_doesnt_exist(&pipeline_info);
+
+                        Ok(pipeline_info)
+                    })
+                    .collect::<Result<Vec<_>, RenderError>>()
+            })
+            .collect::<Result<Vec<_>, RenderError>>()?;
+
+        let upload_start = Instant::now();
+
+        // Pack the geometry of every draw into one upload, vertex offsets have to stay
+        // aligned for set_vertex_buffer and the total sizes for write_buffer
+        let mut vertex_data: Vec<u8> = Vec::new();
+        let mut index_data: Vec<u16> = Vec::new();
+
+        let ranges = passes
+            .iter()
+            .map(|pass| {
+                pass.draws
+                    .iter()
+                    .map(|draw| {
+                        let range = DrawRange {
+                            vertex_offset: vertex_data.len() as u64,
+                            vertex_size: draw.mesh.vertices.len() as u64,
+                            first_index: index_data.len() as u32,
+                            index_count: draw.mesh.indices.len() as u32,
+                        };
+
+                        vertex_data.extend_from_slice(&draw.mesh.vertices);
+                        vertex_data.resize(
+                            wgpu::util::align_to(
+                                vertex_data.len(),
+                                wgpu::VERTEX_STRIDE_ALIGNMENT as usize,
+                            ),
+                            0,
+                        );
+                        index_data.extend_from_slice(&draw.mesh.indices);
+
+                        range
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        index_data.resize(
+            wgpu::util::align_to(
+                index_data.len(),
+                wgpu::COPY_BUFFER_ALIGNMENT as usize / std::mem::size_of::<u16>(),
+            ),
+            0,
+        );
+
+        ctx.ensure_geometry_capacity(
+            vertex_data.len() as u64,
+            (index_data.len() * std::mem::size_of::<u16>()) as u64,
+        );
+
+        ctx.queue.write_buffer(&ctx.vertex_buffer, 0, &vertex_data);
+        ctx.queue
+            .write_buffer(&ctx.index_buffer, 0, bytemuck::cast_slice(&index_data));
+
+        ctx.record_cpu_time("upload", upload_start);
+
+        let timestamps = passes
+            .iter()
+            .map(|pass| {
+                ctx.profiler
+                    .as_mut()
+                    .and_then(|profiler| profiler.allocate(&pass.label))
+            })
+            .collect::<Vec<_>>();
+
+        let mut encoder = ctx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Rendering encoder"),
+            });
+
+        for (((pass, pipeline_infos), ranges), timestamps) in passes
+            .iter()
+            .zip(pipeline_infos)
+            .zip(ranges)
+            .zip(timestamps)
+        {
+            Self::record_pass(ctx, &mut encoder, pass, &pipeline_infos, ranges, timestamps);
+        }
+
+        ctx.queue.submit(std::iter::once(encoder.finish()));
+
+        Ok(())
+    }
+
+    fn record_pass(
+        ctx: &RenderingContext,
+        encoder: &mut wgpu::CommandEncoder,
+        pass: &PassRecord,
+        pipeline_infos: &[RenderPipelineInfo],
+        ranges: Vec<DrawRange>,
+        timestamps: Option<(u32, u32)>,
+    ) {
+        let color_attachments = pass
+            .targets
+            .iter()
+            .map(|target| {
+                let view = &ctx
+                    .textures
+                    .get(*target)
+                    .expect("output texture does not exist")
+                    .target_view();
+
+                Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: match pass.clear {
+                            Some(color) => wgpu::LoadOp::Clear(color),
+                            None => wgpu::LoadOp::Load,
+                        },
+                        store: wgpu::StoreOp::Store,
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let depth_stencil_attachment = pass.depth.as_ref().map(
+            |DepthTextureInfo {
+                 depth_texture,
+                 clear_depth,
+             }| {
+                let depth_texture = &ctx
+                    .textures
+                    .get(*depth_texture)
+                    .expect("depth texture does not exist")
+                    .target_view();
+
+                wgpu::RenderPassDepthStencilAttachment {
+                    view: depth_texture,
+                    depth_ops: Some(wgpu::Operations {
+                        load: match *clear_depth {
+                            true => wgpu::LoadOp::Clear(1.0),
+                            false => wgpu::LoadOp::Load,
+                        },
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }
+            },
+        );
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            timestamp_writes: ctx
+                .profiler
+                .as_ref()
+                .and_then(|profiler| profiler.render_timestamp_writes(timestamps)),
+            occlusion_query_set: pass.occlusion_queries.map(|queries| {
+                &ctx.occlusion_queries
+                    .get(queries)
+                    .expect("occlusion queries do not exist")
+                    .query_set
+            }),
+            label: Some(&pass.label),
+            color_attachments: &color_attachments,
+            depth_stencil_attachment,
+        });
+
+        render_pass.set_index_buffer(ctx.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+
+        let multi_draw = ctx
+            .device
+            .features()
+            .contains(wgpu::Features::MULTI_DRAW_INDIRECT);
+
+        let mut active_query = None;
+
+        for ((draw, pipeline_info), range) in pass.draws.iter().zip(pipeline_infos).zip(ranges) {
+            // Consecutive draws in the same query share one begin/end
+            if draw.occlusion_query != active_query {
+                if active_query.is_some() {
+                    render_pass.end_occlusion_query();
+                }
+                if let Some(query) = draw.occlusion_query {
+                    render_pass.begin_occlusion_query(query);
+                }
+                active_query = draw.occlusion_query;
+            }
+
+            if range.vertex_size == 0 || range.index_count == 0 {
+                continue;
+            }
+
+            let pipeline = ctx.render_pipelines.get(pipeline_info).unwrap();
+
+            render_pass.set_pipeline(pipeline);
+
+            let mut bind_group_idx = 0;
+            for uniform_binding_id in pipeline_info.uniform_binding_ids.iter() {
+                let binding = ctx.uniform_bindings.get(*uniform_binding_id).unwrap();
+
+                render_pass.set_bind_group(bind_group_idx, &binding.bind_group, &[]);
+
+                bind_group_idx += 1;
+            }
+
+            for binding in draw.textures.iter() {
+                let (_, bind_group) = ctx
+                    .textures
+                    .get(binding.texture)
+                    .and_then(|texture| texture.binding(binding))
+                    .unwrap();
+
+                render_pass.set_bind_group(bind_group_idx, bind_group, &[]);
+
+                bind_group_idx += 1;
+            }
+
+            render_pass.set_vertex_buffer(
+                0,
+                ctx.vertex_buffer
+                    .slice(range.vertex_offset..range.vertex_offset + range.vertex_size),
+            );
+
+            match draw.kind {
+                DrawKind::Direct | DrawKind::OcclusionTest => render_pass.draw_indexed(
+                    range.first_index..range.first_index + range.index_count,
+                    0,
+                    0..1,
+                ),
+                DrawKind::Indirect(IndirectArgs {
+                    buffer,
+                    offset,
+                    count,
+                }) => {
+                    let buffer = &ctx
+                        .storage_buffers
+                        .get(buffer)
+                        .expect("indirect buffer does not exist")
+                        .buffer;
+
+                    // The args dont know where the mesh is in the shared index buffer
+                    render_pass.set_index_buffer(
+                        ctx.index_buffer
+                            .slice(range.first_index as u64 * std::mem::size_of::<u16>() as u64..),
+                        wgpu::IndexFormat::Uint16,
+                    );
+
+                    if count > 1 && multi_draw {
+                        render_pass.multi_draw_indexed_indirect(buffer, offset, count);
+                    } else {
+                        for idx in 0..count as u64 {
+                            render_pass.draw_indexed_indirect(
+                                buffer,
+                                offset
+                                    + idx * std::mem::size_of::<DrawIndexedIndirectArgs>() as u64,
+                            );
+                        }
+                    }
+
+                    render_pass
+                        .set_index_buffer(ctx.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                }
+            }
+        }
+
+        if active_query.is_some() {
+            render_pass.end_occlusion_query();
+        }
+    }
+}
+
+pub struct PassBuilder<'c, 'w, 'd> {
+    ctx: &'c mut RenderingContext<'w>,
+    frame: FrameEncoder<'d>,
+    pass: usize,
+}
+
+impl<'c, 'w, 'd> PassBuilder<'c, 'w, 'd> {
+    pub(crate) fn new(
+        ctx: &'c mut RenderingContext<'w>,
+        targets: &[TextureHandle],
+        depth: Option<DepthTextureInfo>,
+        clear: Option<wgpu::Color>,
+    ) -> Self {
+        let mut frame = FrameEncoder::new();
+        let pass = frame.begin_pass("render pass", targets, depth, clear);
+
+        Self { ctx, frame, pass }
+    }
+
+    pub fn draw(
+        &mut self,
+        mesh: &'d PackedMesh,
+        shader: &Shader,
+        uniforms: &[&'d Uniform],
+        textures: &[TextureBinding],
+    ) -> &mut Self {
+        self.frame.draw(
+            self.pass,
+            mesh,
+            shader,
+            uniforms,
+            textures,
+            DrawKind::Direct,
+        );
+
+        self
+    }
+
+    pub fn draw_indirect(
+        &mut self,
+        mesh: &'d PackedMesh,
+        shader: &Shader,
+        uniforms: &[&'d Uniform],
+        textures: &[TextureBinding],
+        indirect: IndirectArgs,
+    ) -> &mut Self {
+        self.frame.draw(
+            self.pass,
+            mesh,
+            shader,
+            uniforms,
+            textures,
+            DrawKind::Indirect(indirect),
+        );
+
+        self
+    }
+
+    // Query set used by begin_occlusion_query, has to be set before any query is begun
+    pub fn occlusion_queries(&mut self, queries: OcclusionQueryHandle) -> &mut Self {
+        self.frame.set_occlusion_queries(self.pass, queries);
+
+        self
+    }
+
+    // Every draw until end_occlusion_query counts towards the given query
+    pub fn begin_occlusion_query(&mut self, query: u32) -> &mut Self {
+        self.frame.begin_occlusion_query(self.pass, query);
+
+        self
+    }
+
+    pub fn end_occlusion_query(&mut self) -> &mut Self {
+        self.frame.end_occlusion_query(self.pass);
+
+        self
+    }
+
+    // Draws without writing color or depth, only counts samples passing the depth test
+    // (usually a bounding box inside of an occlusion query)
+    pub fn draw_occlusion_test(
+        &mut self,
+        mesh: &'d PackedMesh,
+        shader: &Shader,
+        uniforms: &[&'d Uniform],
+    ) -> &mut Self {
+        self.frame.draw(
+            self.pass,
+            mesh,
+            shader,
+            uniforms,
+            &[],
+            DrawKind::OcclusionTest,
+        );
+
+        self
+    }
+
+    pub fn finish(self) -> Result<(), RenderError> {
+        self.frame.submit(self.ctx)
+    }
+}
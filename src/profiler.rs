@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use crate::readback::QueryReadback;
+
+// Passes past this amount in a single frame are simply not timed
+const MAX_TIMED_PASSES: u32 = 64;
+// How many frames can wait for their timestamps to be read back at once
+const FRAMES_IN_FLIGHT: usize = 4;
+
+#[derive(Clone, Debug, Default)]
+pub struct FrameTimings {
+    // Gpu time of each labeled pass in milliseconds, in submission order
+    pub passes: Vec<(String, f64)>,
+    // Cpu time spent on things like uploads and pipeline creation in milliseconds
+    pub cpu: Vec<(String, f64)>,
+}
+
+impl FrameTimings {
+    pub fn pass(&self, label: &str) -> Option<f64> {
+        self.passes
+            .iter()
+            .find(|(pass, _)| pass == label)
+            .map(|(_, ms)| *ms)
+    }
+
+    pub fn gpu_total(&self) -> f64 {
+        self.passes.iter().map(|(_, ms)| ms).sum()
+    }
+}
+
+// Pass labels and cpu timings of a frame travel together with its timestamps
+struct PendingFrame {
+    labels: Vec<String>,
+    cpu: Vec<(String, f64)>,
+}
+
+struct Timestamps {
+    query_set: wgpu::QuerySet,
+    readback: QueryReadback<PendingFrame>,
+}
+
+// Writes timestamps at the start and end of every pass and reads them back a few frames
+// later without stalling. Without TIMESTAMP_QUERY only the cpu timings are reported
+pub struct Profiler {
+    timestamps: Option<Timestamps>,
+    timestamp_period: f32,
+
+    labels: Vec<String>,
+    cpu: Vec<(String, f64)>,
+    last: Option<FrameTimings>,
+}
+
+impl Profiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let timestamps = device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| Timestamps {
+                query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                    label: Some("profiler queries"),
+                    ty: wgpu::QueryType::Timestamp,
+                    count: MAX_TIMED_PASSES * 2,
+                }),
+                readback: QueryReadback::new(device, MAX_TIMED_PASSES * 2, FRAMES_IN_FLIGHT),
+            });
+
+        crate::debug!("Created profiler, gpu timestamps: {}", timestamps.is_some());
+
+        Self {
+            timestamps,
+            timestamp_period: queue.get_timestamp_period(),
+            labels: Vec::new(),
+            cpu: Vec::new(),
+            last: None,
+        }
+    }
+
+    // Reserves the begin and end query for a pass, None if the pass cant be timed
+    pub fn allocate(&mut self, label: &str) -> Option<(u32, u32)> {
+        let timestamps = self.timestamps.as_ref()?;
+
+        if !timestamps.readback.has_free_slot() || self.labels.len() as u32 >= MAX_TIMED_PASSES {
+            return None;
+        }
+
+        let begin = self.labels.len() as u32 * 2;
+        self.labels.push(label.to_owned());
+
+        Some((begin, begin + 1))
+    }
+
+    pub fn render_timestamp_writes(
+        &self,
+        queries: Option<(u32, u32)>,
+    ) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        let (begin, end) = queries?;
+
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set: &self.timestamps.as_ref()?.query_set,
+            beginning_of_pass_write_index: Some(begin),
+            end_of_pass_write_index: Some(end),
+        })
+    }
+
+    pub fn compute_timestamp_writes(
+        &self,
+        queries: Option<(u32, u32)>,
+    ) -> Option<wgpu::ComputePassTimestampWrites<'_>> {
+        let (begin, end) = queries?;
+
+        Some(wgpu::ComputePassTimestampWrites {
+            query_set: &self.timestamps.as_ref()?.query_set,
+            beginning_of_pass_write_index: Some(begin),
+            end_of_pass_write_index: Some(end),
+        })
+    }
+
+    pub fn record_cpu(&mut self, label: &str, duration: Duration) {
+        let ms = duration.as_secs_f64() * 1000.0;
+
+        match self.cpu.iter_mut().find(|(existing, _)| existing == label) {
+            Some((_, total)) => *total += ms,
+            None => self.cpu.push((label.to_owned(), ms)),
+        }
+    }
+
+    // Latest frame whose timings finished resolving
+    pub fn timings(&self) -> Option<&FrameTimings> {
+        self.last.as_ref()
+    }
+
+    pub fn end_frame(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let labels = std::mem::take(&mut self.labels);
+        let cpu = std::mem::take(&mut self.cpu);
+
+        let Some(timestamps) = self.timestamps.as_mut() else {
+            self.last = Some(FrameTimings {
+                passes: Vec::new(),
+                cpu,
+            });
+            return;
+        };
+
+        if let Some((ticks, PendingFrame { labels, cpu })) = timestamps.readback.collect(device) {
+            let passes = labels
+                .into_iter()
+                .zip(ticks.chunks_exact(2))
+                .map(|(label, ticks)| {
+                    let elapsed = ticks[1].wrapping_sub(ticks[0]) as f64;
+                    (label, elapsed * self.timestamp_period as f64 / 1_000_000.0)
+                })
+                .collect();
+
+            self.last = Some(FrameTimings { passes, cpu });
+        }
+
+        // Frames without timed passes (all slots were busy) arent reported at all
+        if !labels.is_empty() {
+            let query_count = labels.len() as u32 * 2;
+            timestamps.readback.resolve(
+                device,
+                queue,
+                &timestamps.query_set,
+                query_count,
+                PendingFrame { labels, cpu },
+            );
+        }
+    }
+}
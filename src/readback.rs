@@ -0,0 +1,232 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::texture::{data_layout, Texture};
+
+struct ReadbackSlot<T> {
+    buffer: wgpu::Buffer,
+    mapped: Arc<AtomicBool>,
+    pending: bool,
+    frame: u64,
+    query_count: u32,
+    payload: Option<T>,
+}
+
+// Resolves a query set into one of a few mappable buffers and hands the values back once
+// the gpu is done with them, so reading queries never stalls the frame. `T` is whatever
+// the owner wants to get back together with the values (labels, timings...)
+pub(crate) struct QueryReadback<T> {
+    resolve_buffer: wgpu::Buffer,
+    slots: Vec<ReadbackSlot<T>>,
+    frame: u64,
+    last_frame: u64,
+}
+
+impl<T> QueryReadback<T> {
+    pub fn new(device: &wgpu::Device, max_queries: u32, frames_in_flight: usize) -> Self {
+        let size = (max_queries * wgpu::QUERY_SIZE) as u64;
+
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("query resolve buffer"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let slots = (0..frames_in_flight)
+            .map(|_| ReadbackSlot {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("query readback buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                mapped: Arc::new(AtomicBool::new(false)),
+                pending: false,
+                frame: 0,
+                query_count: 0,
+                payload: None,
+            })
+            .collect();
+
+        Self {
+            resolve_buffer,
+            slots,
+            frame: 0,
+            last_frame: 0,
+        }
+    }
+
+    pub fn has_free_slot(&self) -> bool {
+        self.slots.iter().any(|slot| !slot.pending)
+    }
+
+    // Returns false (and drops the payload) if every slot is still waiting on the gpu
+    pub fn resolve(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        query_set: &wgpu::QuerySet,
+        query_count: u32,
+        payload: T,
+    ) -> bool {
+        self.frame += 1;
+
+        let Some(slot) = self.slots.iter_mut().find(|slot| !slot.pending) else {
+            return false;
+        };
+
+        let size = (query_count * wgpu::QUERY_SIZE) as u64;
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Query resolve encoder"),
+        });
+        encoder.resolve_query_set(query_set, 0..query_count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &slot.buffer, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let mapped = slot.mapped.clone();
+        mapped.store(false, Ordering::Release);
+        slot.buffer
+            .slice(..size)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                if result.is_ok() {
+                    mapped.store(true, Ordering::Release);
+                }
+            });
+
+        slot.pending = true;
+        slot.frame = self.frame;
+        slot.query_count = query_count;
+        slot.payload = Some(payload);
+
+        true
+    }
+
+    // Newest values which finished reading back since the last call
+    pub fn collect(&mut self, device: &wgpu::Device) -> Option<(Vec<u64>, T)> {
+        device.poll(wgpu::Maintain::Poll);
+
+        let mut newest = None;
+
+        for slot in self.slots.iter_mut() {
+            if !slot.pending || !slot.mapped.load(Ordering::Acquire) {
+                continue;
+            }
+
+            let size = (slot.query_count * wgpu::QUERY_SIZE) as u64;
+            let values: Vec<u64> = {
+                let data = slot.buffer.slice(..size).get_mapped_range();
+                bytemuck::cast_slice(&data).to_vec()
+            };
+            slot.buffer.unmap();
+            slot.pending = false;
+
+            // Slots can finish out of order, only keep the newest frame
+            if slot.frame > self.last_frame {
+                self.last_frame = slot.frame;
+                newest = slot.payload.take().map(|payload| (values, payload));
+            }
+        }
+
+        newest
+    }
+}
+
+// Copy of the first mip level of a texture's first layer in a mappable buffer. Rows of
+// buffer copies are padded to COPY_BYTES_PER_ROW_ALIGNMENT, data() strips the padding
+pub(crate) struct TextureReadback {
+    buffer: wgpu::Buffer,
+    mapped: Arc<AtomicBool>,
+    failed: Arc<AtomicBool>,
+    bytes_per_row: u32,
+    padded_bytes_per_row: u32,
+    rows: u32,
+}
+
+impl TextureReadback {
+    // None for formats without a copyable color aspect
+    pub fn new(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &Texture,
+    ) -> Option<Self> {
+        if texture.format.has_depth_aspect() || texture.format.has_stencil_aspect() {
+            return None;
+        }
+
+        let (bytes_per_row, rows) = data_layout(texture.format, texture.dimensions)?;
+        let padded_bytes_per_row =
+            wgpu::util::align_to(bytes_per_row, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("texture readback buffer"),
+            size: (padded_bytes_per_row * rows) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        encoder.copy_texture_to_buffer(
+            texture.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(rows),
+                },
+            },
+            wgpu::Extent3d {
+                width: texture.dimensions.0,
+                height: texture.dimensions.1,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        Some(Self {
+            buffer,
+            mapped: Arc::new(AtomicBool::new(false)),
+            failed: Arc::new(AtomicBool::new(false)),
+            bytes_per_row,
+            padded_bytes_per_row,
+            rows,
+        })
+    }
+
+    // Has to be called after the copy was submitted
+    pub fn map(&self) {
+        let (mapped, failed) = (self.mapped.clone(), self.failed.clone());
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| match result {
+                Ok(()) => mapped.store(true, Ordering::Release),
+                Err(_) => failed.store(true, Ordering::Release),
+            });
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        self.mapped.load(Ordering::Acquire)
+    }
+
+    // The buffer will never be mapped, usually because the device was lost
+    #[cfg(feature = "image")]
+    pub fn is_failed(&self) -> bool {
+        self.failed.load(Ordering::Acquire)
+    }
+
+    // Tightly packed rows, only valid once mapped
+    pub fn data(&self) -> Vec<u8> {
+        let data = {
+            let mapped = self.buffer.slice(..).get_mapped_range();
+            mapped
+                .chunks_exact(self.padded_bytes_per_row as usize)
+                .take(self.rows as usize)
+                .flat_map(|row| &row[..self.bytes_per_row as usize])
+                .copied()
+                .collect()
+        };
+        self.buffer.unmap();
+
+        data
+    }
+}
@@ -1,28 +1,246 @@
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
-use std::io::empty;
-use std::rc::Rc;
-use std::sync::Arc;
+use std::sync::{mpsc, Arc};
+use std::time::Instant;
 
-use encase::UniformBuffer;
 use itertools::Itertools;
-use ordered_float::NotNan;
-use wgpu::rwh::{self, HasWindowHandle};
+
 use wgpu::{
-    Device, InstanceDescriptor, InstanceFlags, PipelineCompilationOptions, Queue, Surface,
-    SurfaceConfiguration, SurfaceTarget, TextureFormat, WindowHandle,
+    Device, PipelineCompilationOptions, Queue, Surface, SurfaceConfiguration, TextureFormat,
 };
 use winit::window::Window;
 
-use crate::mesh::{Mesh, PackedMesh, VertexLayoutInfo};
-use crate::shader::{Shader, ShaderModule};
-use crate::texture::Texture;
-use crate::uniform::{self, DynamicInfo, Uniform, UniformBindGroup};
-use crate::vertex::Vertex;
+#[cfg(feature = "image")]
+use crate::capture::Recorder;
+use crate::compute::{
+    BufferHandle, ComputeBinding, ComputeBindingKey, ComputePipelineInfo, StorageBuffer,
+};
+use crate::context::{ContextBuilder, ContextError};
+use crate::display::{display_uniform, DisplayRect, ScaleMode, DISPLAY_SHADER};
+use crate::graph::TransientTexture;
+use crate::mesh::{PackedMesh, VertexLayoutInfo};
+use crate::mipmap::MipmapGenerator;
+use crate::occlusion::{OcclusionQueryHandle, OcclusionQuerySet};
+use crate::pass::{IndirectArgs, PassBuilder};
+use crate::profiler::{FrameTimings, Profiler};
+use crate::readback::TextureReadback;
+use crate::resources::{LoadedResource, ResourceLoader};
+use crate::sampler::{Sampler, SamplerDesc, SamplerHandle, TextureBinding};
+use crate::shader::{ComputeShader, Shader, ShaderModule};
+use crate::slotmap::{SlotHandle, SlotMap};
+use crate::surface::{RenderSurface, SurfaceId};
+use crate::texture::{
+    data_size, format_features, full_mip_count, Texture, TextureDesc, TextureKind, TextureLayoutKey,
+};
+use crate::tonemap::{is_hdr_format, TonemapOperator, Tonemapping};
+use crate::uniform::{DynamicInfo, Uniform, UniformBindGroup};
+use crate::upload::TextureUploads;
 
 pub static FULLSCREEN_SHADER: &str = include_str!("fullscreen.wgsl");
 
-pub type TextureHandle = usize;
+pub type TextureHandle = SlotHandle;
+
+#[derive(Debug)]
+pub enum RenderError {
+    // The texture was destroyed, its slot might already hold a different texture
+    StaleTexture(TextureHandle),
+    // The surface was removed
+    StaleSurface(SurfaceId),
+    // No sampler was created with the handle
+    StaleSampler(SamplerHandle),
+    // Sampler whose binding type the texture format doesnt support, like a filtering sampler
+    // on an R32Float texture or a comparison sampler on a color texture
+    IncompatibleSampler {
+        texture: TextureHandle,
+        sampler: SamplerHandle,
+    },
+    InvalidMipLevel {
+        texture: TextureHandle,
+        level: u32,
+    },
+    InvalidLayer {
+        texture: TextureHandle,
+        layer: u32,
+    },
+    UnsupportedFormat(wgpu::TextureFormat),
+    // Device features the operation needs but the context was created without
+    MissingFeatures(wgpu::Features),
+    // Anisotropy has to be at least 1, above that every filter of the sampler has to be linear
+    InvalidAnisotropy(u16),
+    // Compressed textures have to be a whole number of blocks in size
+    InvalidDimensions {
+        format: wgpu::TextureFormat,
+        dimensions: (u32, u32),
+    },
+    InvalidDataSize {
+        expected: usize,
+        actual: usize,
+    },
+    // No storage buffer was created with the handle
+    StaleBuffer(BufferHandle),
+    // Buffer writes need an offset and size aligned to 4 bytes and have to fit the buffer
+    InvalidBufferWrite {
+        buffer: BufferHandle,
+        offset: u64,
+        size: u64,
+    },
+    // Indirect draws need an offset aligned to 4 bytes and all of their args inside the buffer
+    InvalidIndirectArgs(IndirectArgs),
+    // Packing meshes for indirect draws needs at least one mesh and a single vertex layout
+    InvalidIndirectPack,
+    // Empty region, outside of the mip level or not aligned to the blocks of a compressed format
+    InvalidRegion {
+        texture: TextureHandle,
+        origin: (u32, u32),
+        size: (u32, u32),
+    },
+    MissingUsage(wgpu::TextureUsages),
+    // Reading from storage textures needs adapter specific support for the format
+    UnsupportedStorageAccess {
+        format: wgpu::TextureFormat,
+        access: wgpu::StorageTextureAccess,
+    },
+    // Texture written through a storage binding and also read or rendered to in one pass
+    ConflictingUsage(TextureHandle),
+    // Occlusion query begun on a pass without a query set
+    MissingOcclusionQueries,
+    // No query set was created with the handle
+    StaleOcclusionQueries(OcclusionQueryHandle),
+    // Query sets need between 1 and wgpu::QUERY_SET_MAX_QUERIES queries
+    InvalidQueryCount(u32),
+    // Query begun twice in one pass or outside of the pass's query set
+    InvalidOcclusionQuery(u32),
+    // Mapping the readback buffer failed, usually because the device was lost
+    ReadbackFailed,
+    Surface(wgpu::SurfaceError),
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderError::StaleTexture(handle) => write!(
+                f,
+                "texture {} (generation {}) was destroyed",
+                handle.index(),
+                handle.generation()
+            ),
+            RenderError::StaleSurface(handle) => write!(
+                f,
+                "surface {} (generation {}) was removed",
+                handle.index(),
+                handle.generation()
+            ),
+            RenderError::StaleSampler(sampler) => write!(f, "sampler {sampler} does not exist"),
+            RenderError::IncompatibleSampler { texture, sampler } => write!(
+                f,
+                "texture {} can't be sampled with sampler {sampler}",
+                texture.index()
+            ),
+            RenderError::InvalidMipLevel { texture, level } => {
+                write!(f, "texture {} has no mip level {level}", texture.index())
+            }
+            RenderError::InvalidLayer { texture, layer } => {
+                write!(f, "texture {} has no layer {layer}", texture.index())
+            }
+            RenderError::UnsupportedFormat(format) => {
+                write!(f, "operation not supported for {format:?} textures")
+            }
+            RenderError::MissingFeatures(features) => {
+                write!(f, "device was created without {features:?}")
+            }
+            RenderError::InvalidAnisotropy(clamp) => write!(
+                f,
+                "anisotropy clamp {clamp} needs to be at least 1 and above that linear filtering"
+            ),
+            RenderError::InvalidDimensions { format, dimensions } => write!(
+                f,
+                "{}x{} is not a valid size for {format:?} textures",
+                dimensions.0, dimensions.1
+            ),
+            RenderError::InvalidDataSize { expected, actual } => {
+                write!(f, "expected {expected} bytes of texture data, got {actual}")
+            }
+            RenderError::StaleBuffer(buffer) => {
+                write!(f, "storage buffer {buffer} does not exist")
+            }
+            RenderError::InvalidBufferWrite {
+                buffer,
+                offset,
+                size,
+            } => write!(
+                f,
+                "can't write {size} bytes at offset {offset} to storage buffer {buffer}, both \
+                 have to be multiples of 4 and fit the buffer"
+            ),
+            RenderError::InvalidIndirectArgs(IndirectArgs {
+                buffer,
+                offset,
+                count,
+            }) => write!(
+                f,
+                "can't draw {count} indirect args at offset {offset} of storage buffer {buffer}, \
+                 the offset has to be a multiple of 4 and the args have to fit the buffer"
+            ),
+            RenderError::InvalidIndirectPack => write!(
+                f,
+                "indirect packing needs at least one mesh and all of them with the same layout"
+            ),
+            RenderError::InvalidRegion {
+                texture,
+                origin,
+                size,
+            } => write!(
+                f,
+                "region {}x{} at ({}, {}) doesn't fit texture {}",
+                size.0,
+                size.1,
+                origin.0,
+                origin.1,
+                texture.index()
+            ),
+            RenderError::MissingUsage(usage) => {
+                write!(f, "texture was created without {usage:?}")
+            }
+            RenderError::UnsupportedStorageAccess { format, access } => write!(
+                f,
+                "{format:?} textures can't be bound as {access:?} storage textures"
+            ),
+            RenderError::ConflictingUsage(handle) => write!(
+                f,
+                "texture {} is written as a storage texture and used otherwise in the same pass",
+                handle.index()
+            ),
+            RenderError::MissingOcclusionQueries => {
+                write!(
+                    f,
+                    "occlusion query begun on a pass without occlusion queries"
+                )
+            }
+            RenderError::StaleOcclusionQueries(queries) => {
+                write!(f, "occlusion queries {queries} do not exist")
+            }
+            RenderError::InvalidQueryCount(count) => write!(
+                f,
+                "can't create {count} occlusion queries, a set holds 1 to {}",
+                wgpu::QUERY_SET_MAX_QUERIES
+            ),
+            RenderError::InvalidOcclusionQuery(query) => write!(
+                f,
+                "occlusion query {query} is used twice in one pass or outside of its query set"
+            ),
+            RenderError::ReadbackFailed => write!(f, "couldn't map the readback buffer"),
+            RenderError::Surface(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+impl From<wgpu::SurfaceError> for RenderError {
+    fn from(err: wgpu::SurfaceError) -> Self {
+        RenderError::Surface(err)
+    }
+}
 
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
 pub struct Color {
@@ -70,18 +288,30 @@ pub struct DepthTextureInfo {
 pub struct RenderPassInfo<'a> {
     pub shader: Shader,
     pub uniforms: Vec<&'a Uniform>,
-    pub textures: Vec<TextureHandle>,
+    pub textures: Vec<TextureBinding>,
     pub output_texture: TextureHandle,
     pub depth: Option<DepthTextureInfo>,
     pub clear: Option<wgpu::Color>,
 }
 
 pub struct RenderingContext<'a> {
-    pub surface: Surface<'a>,
-    pub swapchain_format: TextureFormat,
-    pub queue: Queue,
-    pub device: Device,
-    pub config: SurfaceConfiguration,
+    pub instance: wgpu::Instance,
+    pub surfaces: SlotMap<RenderSurface<'a>>,
+    // Surface of the window the context was built for, display and depth textures follow
+    // its size
+    pub main_surface: SurfaceId,
+    // Configuration added surfaces start out with, apart from the size
+    pub surface_template: SurfaceConfiguration,
+    pub adapter: Arc<wgpu::Adapter>,
+    // Format of textures made with create_display_texture, HDR_FORMAT for hdr contexts
+    pub target_format: TextureFormat,
+    pub tonemapping: Tonemapping,
+    pub scale_mode: ScaleMode,
+    // Clears the parts of the surface display_tex doesn't cover
+    pub bar_color: wgpu::Color,
+    // Shared with every ResourceLoader
+    pub queue: Arc<Queue>,
+    pub device: Arc<Device>,
 
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
@@ -90,8 +320,24 @@ pub struct RenderingContext<'a> {
     //do it multiple times also avoids an expensive hash of the entire file
     pub shader_modules: HashMap<*const str, wgpu::ShaderModule>,
     pub uniform_bindings: Vec<UniformBindGroup>,
-    pub textures: Vec<super::texture::Texture>,
+    pub textures: SlotMap<Texture>,
+    pub samplers: Vec<Sampler>,
+    pub sampler_cache: HashMap<SamplerDesc, SamplerHandle>,
     pub render_pipelines: HashMap<RenderPipelineInfo, wgpu::RenderPipeline>,
+    pub transient_textures: Vec<TransientTexture>,
+    pub storage_buffers: Vec<StorageBuffer>,
+    pub compute_pipelines: HashMap<ComputePipelineInfo, wgpu::ComputePipeline>,
+    pub profiler: Option<Profiler>,
+    pub occlusion_queries: Vec<OcclusionQuerySet>,
+    pub mipmap_generator: Option<MipmapGenerator>,
+    // Display and depth textures which follow the surface size when auto_resize is on
+    pub surface_textures: Vec<TextureHandle>,
+    pub auto_resize: bool,
+    pub(crate) texture_uploads: TextureUploads,
+    pub(crate) resource_loader: ResourceLoader,
+    pub(crate) loaded_resources: mpsc::Receiver<LoadedResource>,
+    #[cfg(feature = "image")]
+    pub(crate) recorder: Option<Recorder>,
 }
 
 impl<'a> RenderingContext<'a> {
@@ -123,56 +369,29 @@ impl<'a> RenderingContext<'a> {
     // }
 
     pub async fn new(window_size: impl Into<[u32; 2]>, window: Arc<Window>) -> Self {
-        let window_size = window_size.into();
-
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            flags: InstanceFlags::debugging(),
-            gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
-            backends: wgpu::Backends::all(),
-            dx12_shader_compiler: wgpu::Dx12Compiler::Fxc,
-        });
-
-        let surface = instance.create_surface(window).unwrap();
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::LowPower,
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: Some("Indigo device"),
-                    required_features: wgpu::Features::empty(),
-                    required_limits: wgpu::Limits::downlevel_defaults(),
-                },
-                None,
-            )
+        ContextBuilder::new()
+            .build(window_size, window)
             .await
-            .expect("Couldn't create indigo device");
+            .expect("Couldn't create indigo device")
+    }
 
-        let swapchain_format = surface
-            .get_capabilities(&adapter)
-            .formats
-            .into_iter()
-            .find(|format| format.is_srgb())
-            .expect("Couldn't find appropriate surface");
-
-        let config = wgpu::SurfaceConfiguration {
-            desired_maximum_frame_latency: 2,
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: swapchain_format,
-            width: window_size[0],
-            height: window_size[1],
-            present_mode: wgpu::PresentMode::AutoVsync,
-            view_formats: vec![],
-        };
+    // Everything past device and surface creation, shared with ContextBuilder
+    pub(crate) fn from_parts(
+        instance: wgpu::Instance,
+        surface: Surface<'a>,
+        adapter: wgpu::Adapter,
+        device: Device,
+        queue: Queue,
+        config: SurfaceConfiguration,
+        target_format: TextureFormat,
+    ) -> Self {
+        let (adapter, device, queue) = (Arc::new(adapter), Arc::new(device), Arc::new(queue));
+        let (resource_loader, loaded_resources) =
+            ResourceLoader::new(adapter.clone(), device.clone(), queue.clone());
 
-        surface.configure(&device, &config);
+        let surface_template = config.clone();
+        let mut surfaces = SlotMap::new();
+        let main_surface = surfaces.insert(RenderSurface::new(&device, surface, config));
 
         //Completely arbitrary max count copied from some website lol
         //wgpu doesnt seem to have a way to query the max amount of verts per draw call
@@ -195,18 +414,38 @@ impl<'a> RenderingContext<'a> {
         Self {
             queue,
             device,
-            surface,
-            swapchain_format,
+            instance,
+            surfaces,
+            main_surface,
+            surface_template,
+            adapter,
+            target_format,
+            tonemapping: Tonemapping::default(),
+            scale_mode: ScaleMode::default(),
+            bar_color: wgpu::Color::BLACK,
             // pipeline,
-            config,
-
             vertex_buffer,
             index_buffer,
 
             shader_modules: HashMap::new(),
             uniform_bindings: Vec::new(),
-            textures: Vec::new(),
+            textures: SlotMap::new(),
+            samplers: Vec::new(),
+            sampler_cache: HashMap::new(),
             render_pipelines: HashMap::new(),
+            transient_textures: Vec::new(),
+            storage_buffers: Vec::new(),
+            compute_pipelines: HashMap::new(),
+            profiler: None,
+            occlusion_queries: Vec::new(),
+            mipmap_generator: None,
+            surface_textures: Vec::new(),
+            auto_resize: false,
+            texture_uploads: TextureUploads::default(),
+            resource_loader,
+            loaded_resources,
+            #[cfg(feature = "image")]
+            recorder: None,
         }
     }
 
@@ -214,7 +453,7 @@ impl<'a> RenderingContext<'a> {
         &mut self,
         mesh: &PackedMesh,
         render_data: &RenderPassInfo<'b>,
-    ) -> Result<(), wgpu::SurfaceError> {
+    ) -> Result<(), RenderError> {
         let RenderPassInfo {
             shader,
             uniforms,
@@ -224,158 +463,90 @@ impl<'a> RenderingContext<'a> {
             clear,
         } = render_data;
 
-        // Stupid but i dont want mesh to be &mut PackedMesh or PackedMesh
-        let mut mesh = mesh.clone();
-
-        let uniform_binding_ids = self.find_or_create_uniform_bindings(&uniforms);
-
-        for (original_idx, binding_id) in uniform_binding_ids.iter() {
-            let binding = self.uniform_bindings.get(*binding_id).unwrap();
-            // println!(
-            //     "{:?} -- {:?} -- {:?} || {:?}",
-            //     *original_idx,
-            //     binding.min_size,
-            //     binding.max_size,
-            //     &uniforms[*original_idx].data.len()
-            // );
-            binding.update(&self.queue, &uniforms[*original_idx].data);
-        }
-
-        let binding_ids = uniform_binding_ids
-            .into_iter()
-            .sorted_by(|(original_idx_a, _), (original_idx_b, _)| {
-                original_idx_a.cmp(original_idx_b)
-            })
-            .map(|(_, id)| id)
-            .collect::<Vec<_>>();
-
-        let output_format = self
-            .textures
-            .get(*output_texture)
-            .expect("output texture does not exist)")
-            .format
-            .clone();
-
-        let pipeline_info = RenderPipelineInfo {
-            vertex_layout: mesh.layout.clone(),
-            shader: shader.clone(),
-            textures: textures.clone(),
-            depth: match depth {
-                Some(_) => true,
-                None => false,
-            },
-            uniform_binding_ids: binding_ids.clone(),
-            output_format,
-        };
-
-        self.create_pipeline_if_doesnt_exist(&pipeline_info);
-
-        while (mesh.indices.len() % 4 != 0) {
-            mesh.indices.push(*mesh.indices.last().unwrap());
-        }
+        let mut pass = self.begin_pass(&[*output_texture], depth.clone(), *clear);
+        pass.draw(mesh, shader, uniforms, textures);
+        pass.finish()
+    }
 
-        self.queue
-            .write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&mesh.vertices));
+    pub fn render_mesh_indirect<'b>(
+        &mut self,
+        mesh: &PackedMesh,
+        render_data: &RenderPassInfo<'b>,
+        indirect: IndirectArgs,
+    ) -> Result<(), RenderError> {
+        let RenderPassInfo {
+            shader,
+            uniforms,
+            textures,
+            output_texture,
+            depth,
+            clear,
+        } = render_data;
 
-        self.queue
-            .write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(&mesh.indices));
+        let mut pass = self.begin_pass(&[*output_texture], depth.clone(), *clear);
+        pass.draw_indirect(mesh, shader, uniforms, textures, indirect);
+        pass.finish()
+    }
 
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Rendering encoder"),
-            });
+    pub fn begin_pass<'d>(
+        &mut self,
+        targets: &[TextureHandle],
+        depth: Option<DepthTextureInfo>,
+        clear: Option<wgpu::Color>,
+    ) -> PassBuilder<'_, 'a, 'd> {
+        PassBuilder::new(self, targets, depth, clear)
+    }
 
-        let output_tex = &self
-            .textures
-            .get(*output_texture)
-            .expect("output texture does not exist)")
-            .texture_view;
-
-        let depth_stencil_attachment = match depth {
-            None => None,
-            Some(DepthTextureInfo {
-                depth_texture,
-                clear_depth,
-            }) => {
-                let depth_texture = &self
-                    .textures
-                    .get(*depth_texture)
-                    .expect("depth texture does not exist")
-                    .texture_view;
-
-                Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: depth_texture,
-                    depth_ops: Some(wgpu::Operations {
-                        load: match *clear_depth {
-                            true => wgpu::LoadOp::Clear(1.0),
-                            false => wgpu::LoadOp::Load,
-                        },
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                })
+    pub fn display_tex(
+        &mut self,
+        surface: SurfaceId,
+        texture: TextureHandle,
+    ) -> Result<(), RenderError> {
+        // Integer scaling keeps texels sharp, the texture's own sampler would blur them
+        let binding = match self.scale_mode {
+            ScaleMode::Integer => {
+                TextureBinding::new(texture, self.create_sampler(SamplerDesc::nearest())?)
             }
+            _ => texture.into(),
         };
+        let texture_layouts = self.prepare_texture_bindings(&[binding])?;
+        self.flush_texture_uploads();
 
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            timestamp_writes: None,
-            occlusion_query_set: None,
-            label: Some("render pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: output_tex,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: match clear {
-                        Some(color) => wgpu::LoadOp::Clear(*color),
-                        None => wgpu::LoadOp::Load,
-                    },
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment,
-        });
-
-        let pipeline = self.render_pipelines.get(&pipeline_info).unwrap();
-
-        render_pass.set_pipeline(pipeline);
-
-        let mut bind_group_idx = 0;
-        for uniform_binding_id in binding_ids.iter() {
-            let binding = self.uniform_bindings.get(*uniform_binding_id).unwrap();
-
-            render_pass.set_bind_group(bind_group_idx as u32, &binding.bind_group, &[]);
-
-            bind_group_idx += 1;
-        }
-
-        for texture_id in textures.iter() {
-            let texture = self.textures.get(*texture_id).unwrap();
+        let render_surface = self
+            .surfaces
+            .get(surface)
+            .ok_or(RenderError::StaleSurface(surface))?;
 
-            render_pass.set_bind_group(bind_group_idx, &texture.bind_group, &[]);
-
-            bind_group_idx += 1;
+        // Minimized windows have nothing to present to
+        if !render_surface.visible() {
+            return Ok(());
         }
 
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-
-        render_pass.draw_indexed(0..mesh.indices.len() as u32, 0, 0..1);
-
-        drop(render_pass);
-
-        self.queue.submit(std::iter::once(encoder.finish()));
-
-        Ok(())
-    }
-
-    pub fn display_tex(&mut self, texture: TextureHandle) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
+        let output = render_surface.acquire(&self.device)?;
+        let surface_format = render_surface.format();
+        let surface_size = render_surface.size();
         let output_view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
 
-        let fullscreen_shader = self.load_shader(FULLSCREEN_SHADER, "vs", FULLSCREEN_SHADER, "fs");
+        // Sdr textures are copied as is, hdr ones get exposure and unless the surface can show
+        // them directly the tonemapping operator
+        let texture_info = self.textures.get(texture).unwrap();
+        let hdr = is_hdr_format(texture_info.format);
+        let (tonemapping, operator) = match hdr {
+            true if is_hdr_format(surface_format) => (self.tonemapping, TonemapOperator::None),
+            true => (self.tonemapping, self.tonemapping.operator),
+            false => (Tonemapping::default(), TonemapOperator::None),
+        };
+
+        let rect = DisplayRect::new(self.scale_mode, texture_info.dimensions, surface_size);
+        let viewport = rect.clipped(surface_size);
+
+        let uniform = display_uniform(&rect, surface_size, &tonemapping, operator);
+        let (_, binding_id) = self.find_or_create_uniform_bindings(&[&uniform])[0];
+        self.uniform_bindings[binding_id].update(&self.queue, &uniform.data);
+
+        let display_shader = self.load_shader(DISPLAY_SHADER, "vs", DISPLAY_SHADER, "fs");
 
         let mut encoder = self
             .device
@@ -383,15 +554,23 @@ impl<'a> RenderingContext<'a> {
                 label: Some("fullscreen Rendering encoder"),
             });
 
+        let timestamps = self
+            .profiler
+            .as_mut()
+            .and_then(|profiler| profiler.allocate("display"));
+
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            timestamp_writes: None,
+            timestamp_writes: self
+                .profiler
+                .as_ref()
+                .and_then(|profiler| profiler.render_timestamp_writes(timestamps)),
             occlusion_query_set: None,
             label: Some("render pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                 view: &output_view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
+                    load: wgpu::LoadOp::Clear(self.bar_color),
                     store: wgpu::StoreOp::Store,
                 },
             })],
@@ -405,11 +584,13 @@ impl<'a> RenderingContext<'a> {
                 attributes: vec![],
                 total_size: 0,
             },
-            shader: fullscreen_shader,
-            textures: vec![texture],
+            shader: display_shader,
+            textures: vec![binding],
+            texture_layouts,
             depth: false,
-            uniform_binding_ids: vec![],
-            output_format: self.swapchain_format,
+            uniform_binding_ids: vec![binding_id],
+            output_formats: vec![surface_format],
+            test_only: false,
         };
 
         self.create_pipeline_if_doesnt_exist(&pipeline_info);
@@ -417,9 +598,23 @@ impl<'a> RenderingContext<'a> {
 
         render_pass.set_pipeline(pipeline);
 
-        let texture = self.textures.get(texture).unwrap();
+        let (_, displayed) = self
+            .textures
+            .get(texture)
+            .unwrap()
+            .binding(&binding)
+            .unwrap();
 
-        render_pass.set_bind_group(0, &texture.bind_group, &[]);
+        render_pass.set_bind_group(0, &self.uniform_bindings[binding_id].bind_group, &[]);
+        render_pass.set_bind_group(1, displayed, &[]);
+        render_pass.set_viewport(
+            viewport.x,
+            viewport.y,
+            viewport.width,
+            viewport.height,
+            0.0,
+            1.0,
+        );
 
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
 
@@ -427,9 +622,27 @@ impl<'a> RenderingContext<'a> {
 
         drop(render_pass);
 
+        #[cfg(feature = "image")]
+        if surface == self.main_surface {
+            self.record_frame(&mut encoder, texture);
+        }
+
         self.queue.submit(std::iter::once(encoder.finish()));
 
+        #[cfg(feature = "image")]
+        self.poll_recording();
+
+        // Still presentable, but the next frame gets a swapchain matching the surface again
+        let suboptimal = output.suboptimal;
         output.present();
+        if suboptimal {
+            self.surfaces.get(surface).unwrap().configure(&self.device);
+        }
+
+        // With several windows a frame is over once the main one is presented
+        if surface == self.main_surface {
+            self.end_profiler_frame();
+        }
 
         Ok(())
     }
@@ -635,6 +848,230 @@ impl<'a> RenderingContext<'a> {
     //     Ok(())
     // }
 
+    pub fn dispatch(
+        &mut self,
+        shader: &ComputeShader,
+        bindings: &[ComputeBinding],
+        workgroups: (u32, u32, u32),
+    ) -> Result<(), RenderError> {
+        for binding in bindings {
+            if let ComputeBinding::Texture(texture)
+            | ComputeBinding::StorageTexture { texture, .. } = binding
+            {
+                if !self.textures.contains(*texture) {
+                    return Err(RenderError::StaleTexture(*texture));
+                }
+            }
+        }
+
+        self.flush_texture_uploads();
+
+        let uniforms = bindings
+            .iter()
+            .filter_map(|binding| match binding {
+                ComputeBinding::Uniform(uniform) => Some(*uniform),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let uniform_binding_ids = self
+            .find_or_create_uniform_bindings(&uniforms)
+            .into_iter()
+            .sorted_by(|(original_idx_a, _), (original_idx_b, _)| {
+                original_idx_a.cmp(original_idx_b)
+            })
+            .map(|(_, id)| id)
+            .collect::<Vec<_>>();
+
+        for (uniform, binding_id) in uniforms.iter().zip(uniform_binding_ids.iter()) {
+            let binding = self.uniform_bindings.get(*binding_id).unwrap();
+            binding.update(&self.queue, &uniform.data);
+        }
+
+        let mut uniform_binding_ids = uniform_binding_ids.into_iter();
+        let keys = bindings
+            .iter()
+            .map(|binding| {
+                Ok(match binding {
+                    ComputeBinding::Uniform(_) => {
+                        ComputeBindingKey::Uniform(uniform_binding_ids.next().unwrap())
+                    }
+                    ComputeBinding::Buffer { buffer, read_only } => {
+                        if self.storage_buffers.get(*buffer).is_none() {
+                            return Err(RenderError::StaleBuffer(*buffer));
+                        }
+                        ComputeBindingKey::Buffer {
+                            read_only: *read_only,
+                        }
+                    }
+                    ComputeBinding::Texture(texture) => ComputeBindingKey::Texture(
+                        self.textures
+                            .get(*texture)
+                            .ok_or(RenderError::StaleTexture(*texture))?
+                            .layout_key(None),
+                    ),
+                    ComputeBinding::StorageTexture { texture, access } => {
+                        ComputeBindingKey::StorageTexture(
+                            self.prepare_storage_binding(*texture, *access)?,
+                        )
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>, RenderError>>()?;
+
+        let bind_groups = bindings
+            .iter()
+            .zip(keys.iter())
+            .map(|(binding, key)| match (binding, key) {
+                (_, ComputeBindingKey::Uniform(id)) => {
+                    let binding = self.uniform_bindings.get(*id).unwrap();
+                    (&binding.bind_group_layout, &binding.bind_group)
+                }
+                (ComputeBinding::Buffer { buffer, read_only }, _) => {
+                    let buffer = self
+                        .storage_buffers
+                        .get(*buffer)
+                        .expect("storage buffer does not exist");
+                    match read_only {
+                        true => (
+                            &buffer.read_only_bind_group_layout,
+                            &buffer.read_only_bind_group,
+                        ),
+                        false => (&buffer.bind_group_layout, &buffer.bind_group),
+                    }
+                }
+                (ComputeBinding::Texture(texture), _) => {
+                    let texture = self.textures.get(*texture).expect("texture does not exist");
+                    (&texture.bind_group_layout, &texture.bind_group)
+                }
+                (ComputeBinding::StorageTexture { texture, access }, _) => {
+                    let (layout, bind_group) = self
+                        .textures
+                        .get(*texture)
+                        .unwrap()
+                        .storage_bindings
+                        .get(access)
+                        .unwrap();
+                    (layout, bind_group)
+                }
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+
+        let pipeline_info = ComputePipelineInfo {
+            shader: shader.clone(),
+            bindings: keys,
+        };
+
+        if !self.compute_pipelines.contains_key(&pipeline_info) {
+            let start = Instant::now();
+            let layouts = bind_groups
+                .iter()
+                .map(|(layout, _)| *layout)
+                .collect::<Vec<_>>();
+            let pipeline = self.create_compute_pipeline(&pipeline_info, &layouts);
+            self.compute_pipelines
+                .insert(pipeline_info.clone(), pipeline);
+
+            if let Some(profiler) = self.profiler.as_mut() {
+                profiler.record_cpu("pipeline creation", start.elapsed());
+            }
+        }
+
+        let timestamps = self
+            .profiler
+            .as_mut()
+            .and_then(|profiler| profiler.allocate("compute pass"));
+
+        let pipeline = self.compute_pipelines.get(&pipeline_info).unwrap();
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Compute encoder"),
+            });
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("compute pass"),
+            timestamp_writes: self
+                .profiler
+                .as_ref()
+                .and_then(|profiler| profiler.compute_timestamp_writes(timestamps)),
+        });
+
+        compute_pass.set_pipeline(pipeline);
+
+        for (idx, (_, bind_group)) in bind_groups.iter().enumerate() {
+            compute_pass.set_bind_group(idx as u32, bind_group, &[]);
+        }
+
+        compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, workgroups.2);
+
+        drop(compute_pass);
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        Ok(())
+    }
+
+    // Starts timing passes, see frame_timings for the results
+    pub fn enable_profiler(&mut self) {
+        if self.profiler.is_none() {
+            self.profiler = Some(Profiler::new(&self.device, &self.queue));
+        }
+    }
+
+    pub fn disable_profiler(&mut self) {
+        self.profiler = None;
+    }
+
+    // Timings of the latest frame whose gpu timestamps have been read back,
+    // usually a couple frames behind
+    pub fn frame_timings(&self) -> Option<&FrameTimings> {
+        self.profiler.as_ref()?.timings()
+    }
+
+    // Called by display_tex, only needs to be called manually when rendering offscreen
+    pub fn end_profiler_frame(&mut self) {
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.end_frame(&self.device, &self.queue);
+        }
+    }
+
+    pub(crate) fn record_cpu_time(&mut self, label: &str, start: Instant) {
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record_cpu(label, start.elapsed());
+        }
+    }
+
+    pub fn create_occlusion_queries(
+        &mut self,
+        count: u32,
+    ) -> Result<OcclusionQueryHandle, RenderError> {
+        if count == 0 || count > wgpu::QUERY_SET_MAX_QUERIES {
+            return Err(RenderError::InvalidQueryCount(count));
+        }
+
+        let queries = OcclusionQuerySet::new(&self.device, count);
+
+        self.occlusion_queries.push(queries);
+        Ok(self.occlusion_queries.len() - 1)
+    }
+
+    // Copies this frame's results out, they show up in occlusion_results a frame or two later
+    pub fn resolve_occlusion_queries(&mut self, queries: OcclusionQueryHandle) {
+        if let Some(queries) = self.occlusion_queries.get_mut(queries) {
+            queries.resolve(&self.device, &self.queue);
+        }
+    }
+
+    // Samples which passed the depth test for each query, indexed by query
+    pub fn occlusion_results(&mut self, queries: OcclusionQueryHandle) -> Option<&[u64]> {
+        let queries = self.occlusion_queries.get_mut(queries)?;
+
+        Some(queries.results(&self.device))
+    }
+
     pub fn find_or_create_uniform_bindings(
         &mut self,
         uniforms: &[&Uniform],
@@ -659,16 +1096,15 @@ impl<'a> RenderingContext<'a> {
         //of unneccessary big buffers
         uniform_sizes.sort_by(|(_, a, _), (_, b, _)| b.cmp(a));
 
-        'outer: for (sorted_idx, (original_idx, uniform_size, dynamic)) in
-            uniform_sizes.into_iter().enumerate()
-        {
+        'outer: for (original_idx, uniform_size, dynamic) in uniform_sizes.into_iter() {
             for binding_idx in 0..self.uniform_bindings.len() {
                 let binding = self.uniform_bindings.get(binding_idx).unwrap();
 
                 let used = chosen_bindings
                     .iter()
                     .any(|(_, b_idx)| binding_idx == *b_idx);
-                if used {
+                // Layouts of bindings made for other stages arent compatible
+                if used || binding.stages != uniforms[original_idx].stages {
                     continue;
                 }
 
@@ -700,7 +1136,7 @@ impl<'a> RenderingContext<'a> {
 
             //If there was no appropriate binding available then create a new one
             let new_binding =
-                UniformBindGroup::new(&self.device, &self.queue, &uniforms[original_idx]);
+                UniformBindGroup::new(&self.device, &self.queue, uniforms[original_idx]);
             crate::debug!(
                 "Created new binding {:?} --- {:?} - {:?}",
                 &uniforms[original_idx].data.len(),
@@ -715,49 +1151,198 @@ impl<'a> RenderingContext<'a> {
         chosen_bindings
     }
 
-    pub fn update_surface(&mut self, new_size: (u32, u32)) {
-        self.config.width = new_size.0;
-        self.config.height = new_size.1;
-        self.surface.configure(&self.device, &self.config);
-    }
-
-    pub fn create_shader_module_if_doesnt_exist(&mut self, shader_contents: &str) {
-        let shader_location = shader_contents as *const _;
+    // Surfaces start out with the same configuration as the main one, with the format
+    // swapped for one the window supports if needed
+    pub fn add_surface(&mut self, window: Arc<Window>) -> Result<SurfaceId, ContextError> {
+        let size = window.inner_size();
+        let surface = self.instance.create_surface(window)?;
 
-        if self.shader_modules.contains_key(&shader_location) {
-            return;
+        if !self.adapter.is_surface_supported(&surface) {
+            return Err(ContextError::IncompatibleSurface);
         }
 
-        let module = self
-            .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some(shader_contents),
-                source: wgpu::ShaderSource::Wgsl(shader_contents.into()),
-            });
+        let capabilities = surface.get_capabilities(&self.adapter);
+        let formats = capabilities.formats;
+        let format = match formats.contains(&self.surface_template.format) {
+            true => self.surface_template.format,
+            false => formats
+                .iter()
+                .copied()
+                .find(|format| format.is_srgb())
+                .or_else(|| formats.first().copied())
+                .ok_or(ContextError::IncompatibleSurface)?,
+        };
 
-        self.shader_modules.insert(shader_location, module);
-        crate::debug!("Created new shader module");
+        // Same fallbacks as ContextBuilder::build, the window might support other modes
+        let present_mode = match self.surface_template.present_mode {
+            mode @ (wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync) => mode,
+            mode if capabilities.present_modes.contains(&mode) => mode,
+            _ => wgpu::PresentMode::Fifo,
+        };
+
+        let alpha_mode = match capabilities
+            .alpha_modes
+            .contains(&self.surface_template.alpha_mode)
+        {
+            true => self.surface_template.alpha_mode,
+            false => wgpu::CompositeAlphaMode::Auto,
+        };
+
+        let config = SurfaceConfiguration {
+            format,
+            present_mode,
+            alpha_mode,
+            width: size.width,
+            height: size.height,
+            ..self.surface_template.clone()
+        };
+
+        Ok(self
+            .surfaces
+            .insert(RenderSurface::new(&self.device, surface, config)))
     }
 
-    pub fn create_pipeline_if_doesnt_exist(&mut self, pipeline_info: &RenderPipelineInfo) {
-        if self.render_pipelines.contains_key(pipeline_info) {
-            return;
+    // The main surface stays, the context's textures are sized after it
+    pub fn remove_surface(&mut self, surface: SurfaceId) {
+        if surface != self.main_surface {
+            self.surfaces.remove(surface);
         }
+    }
 
-        let uniform_layouts = pipeline_info.uniform_binding_ids.iter().map(|idx| {
-            let binding = &self.uniform_bindings.get(*idx).unwrap();
-            &binding.bind_group_layout
-        });
+    pub fn surface(&self, surface: SurfaceId) -> Option<&RenderSurface<'a>> {
+        self.surfaces.get(surface)
+    }
 
-        let texture_layouts = pipeline_info
-            .textures
-            .iter()
-            .filter_map(|index| self.textures.get(*index))
-            .map(|tex| &tex.bind_group_layout);
+    // Display and depth textures follow the main surface when auto_resize is on
+    pub fn update_surface(&mut self, surface: SurfaceId, new_size: (u32, u32)) {
+        let Some(render_surface) = self.surfaces.get_mut(surface) else {
+            return;
+        };
 
-        let layouts = uniform_layouts.chain(texture_layouts).collect::<Vec<_>>();
+        if !render_surface.resize(&self.device, new_size) || surface != self.main_surface {
+            return;
+        }
 
-        let rp_layout = self
+        if self.auto_resize {
+            let textures = &mut self.textures;
+            self.surface_textures
+                .retain(|handle| textures.contains(*handle));
+
+            for handle in self.surface_textures.iter() {
+                let texture = textures.get_mut(*handle).unwrap();
+                if texture.dimensions != new_size {
+                    texture.resize(&self.device, new_size);
+                }
+            }
+        }
+    }
+
+    // Size textures following the main surface are created with, at least 1x1 while minimized
+    pub fn surface_size(&self) -> (u32, u32) {
+        let (width, height) = self.surfaces.get(self.main_surface).unwrap().size();
+        (width.max(1), height.max(1))
+    }
+
+    // Name, backend and driver of the adapter the context ended up on, mostly for logging
+    pub fn adapter_info(&self) -> wgpu::AdapterInfo {
+        self.adapter.get_info()
+    }
+
+    pub fn set_tonemapping(&mut self, tonemapping: Tonemapping) {
+        self.tonemapping = tonemapping;
+    }
+
+    pub fn set_scale_mode(&mut self, mode: ScaleMode) {
+        self.scale_mode = mode;
+    }
+
+    pub fn set_bar_color(&mut self, color: wgpu::Color) {
+        self.bar_color = color;
+    }
+
+    // Maps a window cursor position to pixel coordinates in a texture shown with display_tex,
+    // None when the cursor is over the bars
+    pub fn cursor_to_texture(
+        &self,
+        surface: SurfaceId,
+        texture: TextureHandle,
+        cursor: (f64, f64),
+    ) -> Option<(f32, f32)> {
+        let dimensions = self.textures.get(texture)?.dimensions;
+        let surface_size = self.surfaces.get(surface)?.size();
+        let rect = DisplayRect::new(self.scale_mode, dimensions, surface_size);
+
+        rect.surface_to_texture((cursor.0 as f32, cursor.1 as f32), dimensions)
+    }
+
+    // Textures created with create_display_texture and create_depth_texture are resized
+    // along with the main surface
+    pub fn set_auto_resize(&mut self, enabled: bool) {
+        self.auto_resize = enabled;
+    }
+
+    // Grows the shared geometry buffers so a whole pass worth of geometry fits in them
+    pub fn ensure_geometry_capacity(&mut self, vertex_size: u64, index_size: u64) {
+        if self.vertex_buffer.size() < vertex_size {
+            self.vertex_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("vertex buffer"),
+                size: vertex_size.next_power_of_two(),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            crate::debug!("Resized vertex buffer to {}B", self.vertex_buffer.size());
+        }
+
+        if self.index_buffer.size() < index_size {
+            self.index_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("index buffer"),
+                size: index_size.next_power_of_two(),
+                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            crate::debug!("Resized index buffer to {}B", self.index_buffer.size());
+        }
+    }
+
+    pub fn create_shader_module_if_doesnt_exist(&mut self, shader_contents: &str) {
+        let shader_location = shader_contents as *const _;
+
+        if self.shader_modules.contains_key(&shader_location) {
+            return;
+        }
+
+        let module = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(shader_contents),
+                source: wgpu::ShaderSource::Wgsl(shader_contents.into()),
+            });
+
+        self.shader_modules.insert(shader_location, module);
+        crate::debug!("Created new shader module");
+    }
+
+    pub fn create_pipeline_if_doesnt_exist(&mut self, pipeline_info: &RenderPipelineInfo) {
+        if self.render_pipelines.contains_key(pipeline_info) {
+            return;
+        }
+
+        let start = Instant::now();
+
+        let uniform_layouts = pipeline_info.uniform_binding_ids.iter().map(|idx| {
+            let binding = &self.uniform_bindings.get(*idx).unwrap();
+            &binding.bind_group_layout
+        });
+
+        let texture_layouts = pipeline_info
+            .textures
+            .iter()
+            .filter_map(|binding| self.textures.get(binding.texture)?.binding(binding))
+            .map(|(layout, _)| layout);
+
+        let layouts = uniform_layouts.chain(texture_layouts).collect::<Vec<_>>();
+
+        let rp_layout = self
             .device
             .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: None,
@@ -780,6 +1365,28 @@ impl<'a> RenderingContext<'a> {
             }
         };
 
+        let targets = pipeline_info
+            .output_formats
+            .iter()
+            .map(|format| {
+                Some(wgpu::ColorTargetState {
+                    format: *format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            operation: wgpu::BlendOperation::Add,
+                            src_factor: wgpu::BlendFactor::SrcAlpha,
+                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        },
+                        alpha: wgpu::BlendComponent::OVER,
+                    }),
+                    write_mask: match pipeline_info.test_only {
+                        true => wgpu::ColorWrites::empty(),
+                        false => wgpu::ColorWrites::ALL,
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+
         let pipeline = self
             .device
             .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
@@ -795,18 +1402,7 @@ impl<'a> RenderingContext<'a> {
                     compilation_options: PipelineCompilationOptions::default(),
                     module: frag_module,
                     entry_point: &pipeline_info.shader.frag_entry,
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: pipeline_info.output_format,
-                        blend: Some(wgpu::BlendState {
-                            color: wgpu::BlendComponent {
-                                operation: wgpu::BlendOperation::Add,
-                                src_factor: wgpu::BlendFactor::SrcAlpha,
-                                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                            },
-                            alpha: wgpu::BlendComponent::OVER,
-                        }),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
+                    targets: &targets,
                 }),
                 primitive: wgpu::PrimitiveState {
                     topology: wgpu::PrimitiveTopology::TriangleList,
@@ -820,7 +1416,7 @@ impl<'a> RenderingContext<'a> {
                 depth_stencil: match pipeline_info.depth {
                     true => Some(wgpu::DepthStencilState {
                         format: Self::DEPTH_FORMAT,
-                        depth_write_enabled: true,
+                        depth_write_enabled: !pipeline_info.test_only,
                         depth_compare: wgpu::CompareFunction::Less,
                         stencil: wgpu::StencilState::default(),
                         bias: wgpu::DepthBiasState::default(),
@@ -838,6 +1434,41 @@ impl<'a> RenderingContext<'a> {
         self.render_pipelines
             .insert(pipeline_info.clone(), pipeline);
         crate::debug!("Created new pipeline");
+
+        self.record_cpu_time("pipeline creation", start);
+    }
+
+    fn create_compute_pipeline(
+        &self,
+        pipeline_info: &ComputePipelineInfo,
+        layouts: &[&wgpu::BindGroupLayout],
+    ) -> wgpu::ComputePipeline {
+        let cp_layout = self
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: layouts,
+                push_constant_ranges: &[],
+            });
+
+        let module = self
+            .shader_modules
+            .get(&pipeline_info.shader.module)
+            .expect("No shader found??");
+
+        let pipeline = self
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: Some(&cp_layout),
+                module,
+                entry_point: &pipeline_info.shader.entry,
+                compilation_options: PipelineCompilationOptions::default(),
+            });
+
+        crate::debug!("Created new compute pipeline");
+
+        pipeline
     }
 
     pub fn load_shader(
@@ -858,12 +1489,329 @@ impl<'a> RenderingContext<'a> {
         )
     }
 
+    pub fn load_compute_shader(&mut self, shader: &str, entry: &str) -> ComputeShader {
+        self.create_shader_module_if_doesnt_exist(shader);
+
+        ComputeShader::new(shader, entry.to_owned())
+    }
+
+    // Size can be bigger than data in which case the rest of the buffer is zeroed
+    pub fn create_storage_buffer(&mut self, data: &[u8], size: u64) -> BufferHandle {
+        let buffer = StorageBuffer::new(&self.device, &self.queue, data, size);
+
+        self.storage_buffers.push(buffer);
+        self.storage_buffers.len() - 1
+    }
+
+    pub fn update_storage_buffer(
+        &mut self,
+        buffer_handle: BufferHandle,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<(), RenderError> {
+        let buffer = self
+            .storage_buffers
+            .get(buffer_handle)
+            .ok_or(RenderError::StaleBuffer(buffer_handle))?;
+
+        let size = data.len() as u64;
+        if offset % wgpu::COPY_BUFFER_ALIGNMENT != 0
+            || size % wgpu::COPY_BUFFER_ALIGNMENT != 0
+            || offset.checked_add(size).is_none_or(|end| end > buffer.size)
+        {
+            return Err(RenderError::InvalidBufferWrite {
+                buffer: buffer_handle,
+                offset,
+                size,
+            });
+        }
+
+        buffer.update(&self.queue, offset, data);
+        Ok(())
+    }
+
+    // Blocks until the gpu is done with all submitted work and copies the buffer back
+    pub fn read_buffer(&self, buffer_handle: BufferHandle) -> Option<Vec<u8>> {
+        let buffer = self.storage_buffers.get(buffer_handle)?;
+
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback buffer"),
+            size: buffer.size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback encoder"),
+            });
+        encoder.copy_buffer_to_buffer(&buffer.buffer, 0, &staging, 0, buffer.size);
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().ok()?.ok()?;
+
+        let data = slice.get_mapped_range().to_vec();
+        staging.unmap();
+
+        Some(data)
+    }
+
+    // Blocks until the gpu is done with all submitted work and copies the first mip level
+    // of the first layer back as tightly packed rows
+    pub fn read_texture(&mut self, texture_handle: TextureHandle) -> Result<Vec<u8>, RenderError> {
+        self.flush_texture_uploads();
+
+        let texture = self
+            .textures
+            .get(texture_handle)
+            .ok_or(RenderError::StaleTexture(texture_handle))?;
+
+        if !texture.usage.contains(wgpu::TextureUsages::COPY_SRC) {
+            return Err(RenderError::MissingUsage(wgpu::TextureUsages::COPY_SRC));
+        }
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Texture readback encoder"),
+            });
+        let readback = TextureReadback::new(&self.device, &mut encoder, texture)
+            .ok_or(RenderError::UnsupportedFormat(texture.format))?;
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        readback.map();
+        self.device.poll(wgpu::Maintain::Wait);
+
+        if !readback.is_mapped() {
+            return Err(RenderError::ReadbackFailed);
+        }
+
+        Ok(readback.data())
+    }
+
+    // Samplers are cached, the same desc always returns the same handle
+    pub fn create_sampler(&mut self, desc: SamplerDesc) -> Result<SamplerHandle, RenderError> {
+        if let Some(handle) = self.sampler_cache.get(&desc) {
+            return Ok(*handle);
+        }
+
+        let linear = [desc.mag_filter, desc.min_filter, desc.mipmap_filter]
+            .iter()
+            .all(|filter| *filter == wgpu::FilterMode::Linear);
+        if desc.anisotropy_clamp == 0 || (desc.anisotropy_clamp > 1 && !linear) {
+            return Err(RenderError::InvalidAnisotropy(desc.anisotropy_clamp));
+        }
+
+        let mut required = wgpu::Features::empty();
+        let address_modes = [
+            desc.address_mode_u,
+            desc.address_mode_v,
+            desc.address_mode_w,
+        ];
+        if address_modes.contains(&wgpu::AddressMode::ClampToBorder) {
+            required |= wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER;
+        }
+        if desc.border_color == Some(wgpu::SamplerBorderColor::Zero) {
+            required |= wgpu::Features::ADDRESS_MODE_CLAMP_TO_ZERO;
+        }
+
+        let missing = required - self.device.features();
+        if !missing.is_empty() {
+            return Err(RenderError::MissingFeatures(missing));
+        }
+
+        self.samplers.push(Sampler::new(&self.device, desc));
+        let handle = self.samplers.len() - 1;
+        self.sampler_cache.insert(desc, handle);
+
+        Ok(handle)
+    }
+
+    // Checks every binding is still valid, creates the bind groups for samplers the
+    // textures weren't drawn with yet and returns the layouts pipelines need for them
+    pub(crate) fn prepare_texture_bindings(
+        &mut self,
+        bindings: &[TextureBinding],
+    ) -> Result<Vec<TextureLayoutKey>, RenderError> {
+        bindings
+            .iter()
+            .map(|binding| {
+                if let Some(access) = binding.storage {
+                    return self.prepare_storage_binding(binding.texture, access);
+                }
+
+                let texture = self
+                    .textures
+                    .get_mut(binding.texture)
+                    .ok_or(RenderError::StaleTexture(binding.texture))?;
+
+                let Some(sampler_handle) = binding.sampler else {
+                    return Ok(texture.layout_key(None));
+                };
+
+                let sampler = self
+                    .samplers
+                    .get(sampler_handle)
+                    .ok_or(RenderError::StaleSampler(sampler_handle))?;
+
+                if !texture.supports_sampler(sampler.desc.binding_type()) {
+                    return Err(RenderError::IncompatibleSampler {
+                        texture: binding.texture,
+                        sampler: sampler_handle,
+                    });
+                }
+
+                texture.create_sampler_binding_if_doesnt_exist(
+                    &self.device,
+                    sampler_handle,
+                    sampler,
+                );
+
+                Ok(texture.layout_key(Some(sampler)))
+            })
+            .collect()
+    }
+
+    // Usages and flags the device supports for a format, more than the guaranteed ones when
+    // the adapter specific format features are enabled
+    pub fn format_features(&self, format: wgpu::TextureFormat) -> wgpu::TextureFormatFeatures {
+        format_features(&self.adapter, &self.device, format)
+    }
+
+    pub(crate) fn prepare_storage_binding(
+        &mut self,
+        texture_handle: TextureHandle,
+        access: wgpu::StorageTextureAccess,
+    ) -> Result<TextureLayoutKey, RenderError> {
+        let texture = self
+            .textures
+            .get(texture_handle)
+            .ok_or(RenderError::StaleTexture(texture_handle))?;
+
+        if !texture.usage.contains(wgpu::TextureUsages::STORAGE_BINDING) {
+            return Err(RenderError::MissingUsage(
+                wgpu::TextureUsages::STORAGE_BINDING,
+            ));
+        }
+
+        // Write only access works for every format that can be storage bound at all
+        let readable = self
+            .format_features(texture.format)
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::STORAGE_READ_WRITE);
+        if access != wgpu::StorageTextureAccess::WriteOnly && !readable {
+            return Err(RenderError::UnsupportedStorageAccess {
+                format: texture.format,
+                access,
+            });
+        }
+
+        let texture = self.textures.get_mut(texture_handle).unwrap();
+        texture.create_storage_binding_if_doesnt_exist(&self.device, access);
+
+        Ok(texture.storage_layout_key(access))
+    }
+
     pub fn create_texture(
         &mut self,
         data: &[u8],
         dimensions: (u32, u32),
         sampler_type: wgpu::FilterMode,
-    ) -> TextureHandle {
+    ) -> Result<TextureHandle, RenderError> {
+        let start = Instant::now();
+
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let texture = Texture::from_data(
+            &self.device,
+            &self.queue,
+            self.format_features(format),
+            data,
+            TextureDesc {
+                dimensions,
+                kind: TextureKind::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::COPY_SRC
+                    // probably should be optional
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                sampler_type,
+                mip_level_count: 1,
+            },
+        )?;
+
+        self.record_cpu_time("texture upload", start);
+
+        Ok(self.textures.insert(texture))
+    }
+
+    // Data has to be tightly packed rows of texels (or blocks for compressed formats),
+    // empty data leaves the texture zeroed
+    pub fn create_texture_with_format(
+        &mut self,
+        data: &[u8],
+        dimensions: (u32, u32),
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+    ) -> Result<TextureHandle, RenderError> {
+        self.create_layered_texture(data, dimensions, TextureKind::D2, format, usage)
+    }
+
+    // Array, 3d and cube textures. Data holds every layer (or depth slice) back to back
+    pub fn create_layered_texture(
+        &mut self,
+        data: &[u8],
+        dimensions: (u32, u32),
+        kind: TextureKind,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+    ) -> Result<TextureHandle, RenderError> {
+        let start = Instant::now();
+
+        let texture = Texture::from_data(
+            &self.device,
+            &self.queue,
+            self.format_features(format),
+            data,
+            TextureDesc {
+                dimensions,
+                kind,
+                format,
+                usage,
+                sampler_type: wgpu::FilterMode::Linear,
+                mip_level_count: 1,
+            },
+        )?;
+
+        self.record_cpu_time("texture upload", start);
+
+        Ok(self.textures.insert(texture))
+    }
+
+    // Same as create_texture but with a full mip chain generated from data on the gpu
+    pub fn create_mipmapped_texture(
+        &mut self,
+        data: &[u8],
+        dimensions: (u32, u32),
+        sampler_type: wgpu::FilterMode,
+    ) -> Result<TextureHandle, RenderError> {
+        let start = Instant::now();
+
+        let expected = data_size(wgpu::TextureFormat::Rgba8UnormSrgb, dimensions).unwrap();
+        if data.len() != expected {
+            return Err(RenderError::InvalidDataSize {
+                expected,
+                actual: data.len(),
+            });
+        }
+
         let texture = Texture::new(
             &self.device,
             &self.queue,
@@ -873,90 +1821,386 @@ impl<'a> RenderingContext<'a> {
             wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::COPY_DST
                 | wgpu::TextureUsages::COPY_SRC
-                // probably should be optional
                 | wgpu::TextureUsages::RENDER_ATTACHMENT,
             sampler_type,
+            full_mip_count(dimensions),
+        );
+
+        self.record_cpu_time("texture upload", start);
+
+        let handle = self.textures.insert(texture);
+        self.generate_mipmaps(handle)
+            .expect("rgba8 textures always support mipmap generation");
+
+        Ok(handle)
+    }
+
+    // Uploads precomputed mip levels, starting with the full size one. Extra levels past
+    // the 1x1 one are ignored
+    pub fn create_texture_with_mips(
+        &mut self,
+        levels: &[&[u8]],
+        dimensions: (u32, u32),
+        sampler_type: wgpu::FilterMode,
+    ) -> Result<TextureHandle, RenderError> {
+        let start = Instant::now();
+
+        let mip_level_count = (levels.len() as u32).clamp(1, full_mip_count(dimensions));
+
+        for (level, data) in levels.iter().enumerate().take(mip_level_count as usize) {
+            let level_size = (
+                (dimensions.0 >> level).max(1),
+                (dimensions.1 >> level).max(1),
+            );
+            let expected = data_size(wgpu::TextureFormat::Rgba8UnormSrgb, level_size).unwrap();
+            if data.len() != expected {
+                return Err(RenderError::InvalidDataSize {
+                    expected,
+                    actual: data.len(),
+                });
+            }
+        }
+
+        let texture = Texture::new(
+            &self.device,
+            &self.queue,
+            levels.first().copied().unwrap_or(&[]),
+            dimensions,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            sampler_type,
+            mip_level_count,
         );
 
-        self.textures.push(texture);
-        let index = self.textures.len() - 1;
+        for (level, data) in levels
+            .iter()
+            .enumerate()
+            .take(mip_level_count as usize)
+            .skip(1)
+        {
+            texture.write_mip_level(&self.queue, level as u32, data);
+        }
+
+        self.record_cpu_time("texture upload", start);
 
-        index
+        Ok(self.textures.insert(texture))
+    }
+
+    // Data for every layer of the level back to back
+    pub fn upload_mip_level(
+        &mut self,
+        texture_handle: TextureHandle,
+        level: u32,
+        data: &[u8],
+    ) -> Result<(), RenderError> {
+        self.upload_texture_data(texture_handle, level, None, data)
+    }
+
+    // Writes a single layer (or depth slice, or cube face) of a mip level
+    pub fn upload_texture_layer(
+        &mut self,
+        texture_handle: TextureHandle,
+        layer: u32,
+        level: u32,
+        data: &[u8],
+    ) -> Result<(), RenderError> {
+        self.upload_texture_data(texture_handle, level, Some(layer), data)
+    }
+
+    fn upload_texture_data(
+        &mut self,
+        texture_handle: TextureHandle,
+        level: u32,
+        layer: Option<u32>,
+        data: &[u8],
+    ) -> Result<(), RenderError> {
+        let start = Instant::now();
+
+        // Queued region writes would otherwise land on top of this one
+        self.flush_texture_uploads();
+
+        let texture = self
+            .textures
+            .get(texture_handle)
+            .ok_or(RenderError::StaleTexture(texture_handle))?;
+
+        if !texture.usage.contains(wgpu::TextureUsages::COPY_DST) {
+            return Err(RenderError::MissingUsage(wgpu::TextureUsages::COPY_DST));
+        }
+
+        if level >= texture.mip_level_count {
+            return Err(RenderError::InvalidMipLevel {
+                texture: texture_handle,
+                level,
+            });
+        }
+
+        let layers = texture.mip_level_layers(level);
+        if let Some(layer) = layer.filter(|layer| *layer >= layers) {
+            return Err(RenderError::InvalidLayer {
+                texture: texture_handle,
+                layer,
+            });
+        }
+
+        let expected = data_size(texture.format, texture.mip_level_size(level))
+            .ok_or(RenderError::UnsupportedFormat(texture.format))?
+            * if layer.is_some() { 1 } else { layers as usize };
+        if data.len() != expected {
+            return Err(RenderError::InvalidDataSize {
+                expected,
+                actual: data.len(),
+            });
+        }
+
+        match layer {
+            Some(layer) => texture.write_layer(&self.queue, layer, level, data),
+            None => texture.write_mip_level(&self.queue, level, data),
+        }
+
+        self.record_cpu_time("texture upload", start);
+
+        Ok(())
+    }
+
+    // Regenerates every mip level from the first one, e.g. after rendering into the texture
+    pub fn generate_mipmaps(&mut self, texture_handle: TextureHandle) -> Result<(), RenderError> {
+        self.flush_texture_uploads();
+
+        let texture = self
+            .textures
+            .get(texture_handle)
+            .ok_or(RenderError::StaleTexture(texture_handle))?;
+
+        if texture.mip_level_count == 1 {
+            return Ok(());
+        }
+
+        if !MipmapGenerator::supports(&self.device, texture) {
+            return Err(RenderError::UnsupportedFormat(texture.format));
+        }
+
+        self.mipmap_generator
+            .get_or_insert_with(|| MipmapGenerator::new(&self.device))
+            .generate(&self.device, &self.queue, texture);
+
+        Ok(())
     }
 
     pub fn try_resize_tex(
         &mut self,
         tex_handle: TextureHandle,
         new_size: (u32, u32),
-    ) -> Result<(), ()> {
-        let tex = self.textures.get_mut(tex_handle).ok_or(())?;
+    ) -> Result<(), RenderError> {
+        let tex = self
+            .textures
+            .get_mut(tex_handle)
+            .ok_or(RenderError::StaleTexture(tex_handle))?;
         tex.resize(&self.device, new_size);
 
         Ok(())
     }
 
+    // Frees the gpu memory right away, the slot gets reused by the next texture but the old
+    // handle stays invalid. Pipelines created with the texture bound are dropped as well
+    pub fn destroy_texture(&mut self, handle: TextureHandle) -> Result<(), RenderError> {
+        let texture = self
+            .textures
+            .remove(handle)
+            .ok_or(RenderError::StaleTexture(handle))?;
+        texture.texture.destroy();
+
+        // Pipelines only depend on the texture layouts, so other textures keep using them
+        self.transient_textures
+            .retain(|transient| transient.handle != handle);
+
+        crate::debug!("Destroyed texture {}", handle.index());
+
+        Ok(())
+    }
+
     pub fn create_display_texture(&mut self) -> TextureHandle {
         let texture = Texture::new(
             &self.device,
             &self.queue,
-            &(0..self.config.width * self.config.height * 4)
-                .map(|_| 0)
-                .collect::<Vec<_>>(),
-            (self.config.width, self.config.height),
-            self.swapchain_format,
+            &[],
+            self.surface_size(),
+            self.target_format,
             wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
                 | wgpu::TextureUsages::RENDER_ATTACHMENT,
             wgpu::FilterMode::Linear,
+            1,
         );
 
-        self.textures.push(texture);
-        let index = self.textures.len() - 1;
-
-        index
+        let handle = self.textures.insert(texture);
+        self.surface_textures.push(handle);
+        handle
     }
+
     pub fn create_depth_texture(&mut self) -> TextureHandle {
         let depth_texture = Texture::new(
             &self.device,
             &self.queue,
             &[],
-            (self.config.width, self.config.height),
+            self.surface_size(),
             wgpu::TextureFormat::Depth32Float,
             wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
             wgpu::FilterMode::Linear,
+            1,
         );
 
-        self.textures.push(depth_texture);
-        let index = self.textures.len() - 1;
+        let handle = self.textures.insert(depth_texture);
+        self.surface_textures.push(handle);
+        handle
+    }
 
-        index
+    // Texture which compute (and fragment) shaders can write to through a storage binding
+    pub fn create_storage_texture(
+        &mut self,
+        dimensions: (u32, u32),
+        format: wgpu::TextureFormat,
+    ) -> TextureHandle {
+        let texture = Texture::new(
+            &self.device,
+            &self.queue,
+            &[],
+            dimensions,
+            format,
+            wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            wgpu::FilterMode::Linear,
+            1,
+        );
+
+        self.textures.insert(texture)
+    }
+
+    pub fn update_texture(
+        &mut self,
+        texture_handle: TextureHandle,
+        data: &[u8],
+    ) -> Result<(), RenderError> {
+        self.upload_texture_data(texture_handle, 0, None, data)
     }
 
-    pub fn update_texture(&mut self, texture_handle: TextureHandle, data: &[u8]) {
-        if let Some(texture) = self.textures.get_mut(texture_handle) {
-            texture.update(&self.queue, data);
+    // Writes a rectangle of one mip level. The write is queued and lands together with all
+    // others of the frame right before the next pass is submitted
+    #[allow(clippy::too_many_arguments)]
+    pub fn update_texture_region(
+        &mut self,
+        texture_handle: TextureHandle,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        data: &[u8],
+        mip_level: u32,
+    ) -> Result<(), RenderError> {
+        let start = Instant::now();
+
+        let texture = self
+            .textures
+            .get(texture_handle)
+            .ok_or(RenderError::StaleTexture(texture_handle))?;
+
+        if !texture.usage.contains(wgpu::TextureUsages::COPY_DST) {
+            return Err(RenderError::MissingUsage(wgpu::TextureUsages::COPY_DST));
         }
+
+        if mip_level >= texture.mip_level_count {
+            return Err(RenderError::InvalidMipLevel {
+                texture: texture_handle,
+                level: mip_level,
+            });
+        }
+
+        let (level_width, level_height) = texture.mip_level_size(mip_level);
+        let (block_width, block_height) = texture.format.block_dimensions();
+
+        // Compressed regions have to cover whole blocks unless they end at the edge, empty
+        // ones would have zero sized rows
+        let fits = width > 0
+            && height > 0
+            && x.checked_add(width).is_some_and(|end| end <= level_width)
+            && y.checked_add(height).is_some_and(|end| end <= level_height);
+        let aligned = x % block_width == 0
+            && y % block_height == 0
+            && (width % block_width == 0 || x + width == level_width)
+            && (height % block_height == 0 || y + height == level_height);
+
+        if !fits || !aligned {
+            return Err(RenderError::InvalidRegion {
+                texture: texture_handle,
+                origin: (x, y),
+                size: (width, height),
+            });
+        }
+
+        let expected = data_size(texture.format, (width, height))
+            .ok_or(RenderError::UnsupportedFormat(texture.format))?;
+        if data.len() != expected {
+            return Err(RenderError::InvalidDataSize {
+                expected,
+                actual: data.len(),
+            });
+        }
+
+        self.texture_uploads.push(
+            texture_handle,
+            texture,
+            mip_level,
+            (x, y),
+            (width, height),
+            data,
+        );
+
+        self.record_cpu_time("texture upload", start);
+
+        Ok(())
+    }
+
+    // Submits the queued region writes now instead of with the next pass
+    pub fn flush_texture_uploads(&mut self) {
+        if self.texture_uploads.is_empty() {
+            return;
+        }
+
+        let start = Instant::now();
+
+        self.texture_uploads
+            .flush(&self.device, &self.queue, &self.textures);
+
+        self.record_cpu_time("texture upload", start);
     }
 }
 
 #[derive(Eq, Clone)]
 pub struct RenderPipelineInfo {
-    vertex_layout: VertexLayoutInfo,
-    shader: Shader,
-    textures: Vec<TextureHandle>,
-    output_format: wgpu::TextureFormat,
-    depth: bool,
-    uniform_binding_ids: Vec<usize>,
+    pub(crate) vertex_layout: VertexLayoutInfo,
+    pub(crate) shader: Shader,
+    pub(crate) textures: Vec<TextureBinding>,
+    pub(crate) texture_layouts: Vec<TextureLayoutKey>,
+    pub(crate) output_formats: Vec<wgpu::TextureFormat>,
+    pub(crate) depth: bool,
+    pub(crate) uniform_binding_ids: Vec<usize>,
+    pub(crate) test_only: bool,
 }
 
 impl PartialEq for RenderPipelineInfo {
     fn eq(&self, other: &Self) -> bool {
         self.vertex_layout == other.vertex_layout
             && self.shader == other.shader
-            && self.textures.len() == other.textures.len()
+            && self.texture_layouts == other.texture_layouts
             && self.uniform_binding_ids == other.uniform_binding_ids
             && self.depth == other.depth
-            && self.output_format == other.output_format
+            && self.output_formats == other.output_formats
+            && self.test_only == other.test_only
     }
 }
 
@@ -964,11 +2208,11 @@ impl Hash for RenderPipelineInfo {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.vertex_layout.hash(state);
         self.shader.hash(state);
-        self.textures.len().hash(state);
-        self.shader.hash(state);
+        self.texture_layouts.hash(state);
         self.uniform_binding_ids.hash(state);
         self.depth.hash(state);
-        self.output_format.hash(state);
+        self.output_formats.hash(state);
+        self.test_only.hash(state);
     }
 }
 
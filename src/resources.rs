@@ -0,0 +1,186 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+
+use wgpu::{Adapter, Device, Queue};
+
+use crate::compute::{BufferHandle, StorageBuffer};
+use crate::renderer::{RenderError, RenderingContext, TextureHandle};
+use crate::texture::{format_features, Texture, TextureDesc, TextureKind};
+
+// Identifies a resource requested from a ResourceLoader until the context registers it
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LoadId(u64);
+
+pub(crate) enum LoadedResource {
+    Texture(LoadId, Texture),
+    Buffer(LoadId, StorageBuffer),
+    Shader(LoadId, &'static str, wgpu::ShaderModule),
+}
+
+// What register_loaded moved into the context
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Loaded {
+    Texture { id: LoadId, handle: TextureHandle },
+    Buffer { id: LoadId, handle: BufferHandle },
+    // The module is cached, load_shader with the same source no longer compiles it
+    Shader { id: LoadId },
+}
+
+// Creates gpu resources from other threads. The resources are sent back to the context
+// which hands out handles for them in register_loaded
+#[derive(Clone)]
+pub struct ResourceLoader {
+    adapter: Arc<Adapter>,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    sender: mpsc::Sender<LoadedResource>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl ResourceLoader {
+    pub(crate) fn new(
+        adapter: Arc<Adapter>,
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+    ) -> (Self, mpsc::Receiver<LoadedResource>) {
+        let (sender, receiver) = mpsc::channel();
+
+        let loader = Self {
+            adapter,
+            device,
+            queue,
+            sender,
+            next_id: Arc::new(AtomicU64::new(0)),
+        };
+
+        (loader, receiver)
+    }
+
+    fn next_id(&self) -> LoadId {
+        LoadId(self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+
+    // A dropped context just means nobody registers the resource anymore
+    fn send(&self, resource: LoadedResource) {
+        let _ = self.sender.send(resource);
+    }
+
+    // Same texture RenderingContext::create_texture makes
+    pub fn create_texture(
+        &self,
+        data: &[u8],
+        dimensions: (u32, u32),
+        sampler_type: wgpu::FilterMode,
+    ) -> Result<LoadId, RenderError> {
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let texture = Texture::from_data(
+            &self.device,
+            &self.queue,
+            format_features(&self.adapter, &self.device, format),
+            data,
+            TextureDesc {
+                dimensions,
+                kind: TextureKind::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                sampler_type,
+                mip_level_count: 1,
+            },
+        )?;
+
+        let id = self.next_id();
+        self.send(LoadedResource::Texture(id, texture));
+        Ok(id)
+    }
+
+    // Same rules as RenderingContext::create_layered_texture
+    pub fn create_layered_texture(
+        &self,
+        data: &[u8],
+        dimensions: (u32, u32),
+        kind: TextureKind,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+    ) -> Result<LoadId, RenderError> {
+        let texture = Texture::from_data(
+            &self.device,
+            &self.queue,
+            format_features(&self.adapter, &self.device, format),
+            data,
+            TextureDesc {
+                dimensions,
+                kind,
+                format,
+                usage,
+                sampler_type: wgpu::FilterMode::Linear,
+                mip_level_count: 1,
+            },
+        )?;
+
+        let id = self.next_id();
+        self.send(LoadedResource::Texture(id, texture));
+        Ok(id)
+    }
+
+    // Size can be bigger than data in which case the rest of the buffer is zeroed
+    pub fn create_storage_buffer(&self, data: &[u8], size: u64) -> LoadId {
+        let id = self.next_id();
+
+        let buffer = StorageBuffer::new(&self.device, &self.queue, data, size);
+
+        self.send(LoadedResource::Buffer(id, buffer));
+        id
+    }
+
+    // Compiles the module ahead of time, shaders are cached by the location of their source
+    // so only static sources can be loaded from other threads
+    pub fn load_shader(&self, shader: &'static str) -> LoadId {
+        let id = self.next_id();
+
+        let module = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(shader),
+                source: wgpu::ShaderSource::Wgsl(shader.into()),
+            });
+
+        self.send(LoadedResource::Shader(id, shader, module));
+        id
+    }
+}
+
+impl<'a> RenderingContext<'a> {
+    pub fn resource_loader(&self) -> ResourceLoader {
+        self.resource_loader.clone()
+    }
+
+    // Moves everything ResourceLoaders finished since the last call into the context, meant
+    // to be called once per frame
+    pub fn register_loaded(&mut self) -> Vec<Loaded> {
+        self.loaded_resources
+            .try_iter()
+            .map(|resource| match resource {
+                LoadedResource::Texture(id, texture) => Loaded::Texture {
+                    id,
+                    handle: self.textures.insert(texture),
+                },
+                LoadedResource::Buffer(id, buffer) => {
+                    self.storage_buffers.push(buffer);
+                    Loaded::Buffer {
+                        id,
+                        handle: self.storage_buffers.len() - 1,
+                    }
+                }
+                LoadedResource::Shader(id, shader, module) => {
+                    self.shader_modules
+                        .entry(shader as *const _)
+                        .or_insert(module);
+                    Loaded::Shader { id }
+                }
+            })
+            .collect()
+    }
+}
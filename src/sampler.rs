@@ -0,0 +1,191 @@
+use std::hash::{Hash, Hasher};
+
+pub type SamplerHandle = usize;
+
+// Everything about how a texture is sampled. Samplers are cached on the context by this, so
+// asking for the same desc twice hands back the same sampler
+#[derive(Copy, Clone, Debug)]
+pub struct SamplerDesc {
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub address_mode_w: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    pub lod_min_clamp: f32,
+    pub lod_max_clamp: f32,
+    // Values above 1 require every filter to be linear
+    pub anisotropy_clamp: u16,
+    // Turns this into a comparison sampler, mostly for shadow maps
+    pub compare: Option<wgpu::CompareFunction>,
+    // Only used with AddressMode::ClampToBorder, which needs ADDRESS_MODE_CLAMP_TO_BORDER.
+    // The Zero color additionally needs ADDRESS_MODE_CLAMP_TO_ZERO
+    pub border_color: Option<wgpu::SamplerBorderColor>,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        Self::filtered(wgpu::FilterMode::Linear)
+    }
+}
+
+impl SamplerDesc {
+    // Same filter everywhere and clamped to the edge, what textures get by default
+    pub fn filtered(filter: wgpu::FilterMode) -> Self {
+        Self {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: filter,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 32.0,
+            anisotropy_clamp: 1,
+            compare: None,
+            border_color: None,
+        }
+    }
+
+    pub fn nearest() -> Self {
+        Self::filtered(wgpu::FilterMode::Nearest)
+    }
+
+    pub fn linear() -> Self {
+        Self::filtered(wgpu::FilterMode::Linear)
+    }
+
+    pub fn address_mode(mut self, mode: wgpu::AddressMode) -> Self {
+        self.address_mode_u = mode;
+        self.address_mode_v = mode;
+        self.address_mode_w = mode;
+        self
+    }
+
+    pub fn comparison(compare: wgpu::CompareFunction) -> Self {
+        Self {
+            compare: Some(compare),
+            ..Self::linear()
+        }
+    }
+
+    pub fn binding_type(&self) -> wgpu::SamplerBindingType {
+        if self.compare.is_some() {
+            return wgpu::SamplerBindingType::Comparison;
+        }
+
+        let filters = [self.mag_filter, self.min_filter, self.mipmap_filter];
+        match filters.contains(&wgpu::FilterMode::Linear) {
+            true => wgpu::SamplerBindingType::Filtering,
+            false => wgpu::SamplerBindingType::NonFiltering,
+        }
+    }
+
+    pub fn descriptor(&self) -> wgpu::SamplerDescriptor<'static> {
+        wgpu::SamplerDescriptor {
+            label: None,
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: self.address_mode_w,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            lod_min_clamp: self.lod_min_clamp,
+            lod_max_clamp: self.lod_max_clamp,
+            compare: self.compare,
+            anisotropy_clamp: self.anisotropy_clamp,
+            border_color: self.border_color,
+        }
+    }
+}
+
+impl PartialEq for SamplerDesc {
+    fn eq(&self, other: &Self) -> bool {
+        self.address_mode_u == other.address_mode_u
+            && self.address_mode_v == other.address_mode_v
+            && self.address_mode_w == other.address_mode_w
+            && self.mag_filter == other.mag_filter
+            && self.min_filter == other.min_filter
+            && self.mipmap_filter == other.mipmap_filter
+            && self.lod_min_clamp.to_bits() == other.lod_min_clamp.to_bits()
+            && self.lod_max_clamp.to_bits() == other.lod_max_clamp.to_bits()
+            && self.anisotropy_clamp == other.anisotropy_clamp
+            && self.compare == other.compare
+            && self.border_color == other.border_color
+    }
+}
+
+impl Eq for SamplerDesc {}
+
+impl Hash for SamplerDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.address_mode_u.hash(state);
+        self.address_mode_v.hash(state);
+        self.address_mode_w.hash(state);
+        self.mag_filter.hash(state);
+        self.min_filter.hash(state);
+        self.mipmap_filter.hash(state);
+        self.lod_min_clamp.to_bits().hash(state);
+        self.lod_max_clamp.to_bits().hash(state);
+        self.anisotropy_clamp.hash(state);
+        self.compare.hash(state);
+        self.border_color.hash(state);
+    }
+}
+
+pub struct Sampler {
+    pub desc: SamplerDesc,
+    pub sampler: wgpu::Sampler,
+}
+
+impl Sampler {
+    pub fn new(device: &wgpu::Device, desc: SamplerDesc) -> Self {
+        let sampler = device.create_sampler(&desc.descriptor());
+
+        crate::debug!("Created new sampler");
+
+        Self { desc, sampler }
+    }
+}
+
+// A texture together with the sampler it is read through, without one the texture's own
+// sampler is used. Storage bindings have no sampler, shaders load and store texels directly
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureBinding {
+    pub texture: crate::renderer::TextureHandle,
+    pub sampler: Option<SamplerHandle>,
+    pub storage: Option<wgpu::StorageTextureAccess>,
+}
+
+impl TextureBinding {
+    pub fn new(texture: crate::renderer::TextureHandle, sampler: SamplerHandle) -> Self {
+        Self {
+            texture,
+            sampler: Some(sampler),
+            storage: None,
+        }
+    }
+
+    // Needs a texture created with STORAGE_BINDING usage. A texture written through a
+    // storage binding can't be sampled or rendered to in the same pass
+    pub fn storage(
+        texture: crate::renderer::TextureHandle,
+        access: wgpu::StorageTextureAccess,
+    ) -> Self {
+        Self {
+            texture,
+            sampler: None,
+            storage: Some(access),
+        }
+    }
+}
+
+impl From<crate::renderer::TextureHandle> for TextureBinding {
+    fn from(texture: crate::renderer::TextureHandle) -> Self {
+        Self {
+            texture,
+            sampler: None,
+            storage: None,
+        }
+    }
+}
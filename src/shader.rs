@@ -40,3 +40,18 @@ impl Shader {
         }
     }
 }
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ComputeShader {
+    pub module: *const str,
+    pub entry: String,
+}
+
+impl ComputeShader {
+    pub fn new(path: *const str, entry: String) -> Self {
+        Self {
+            module: path,
+            entry,
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use wgpu::{Surface, SurfaceConfiguration, TextureFormat};
+
+use crate::slotmap::SlotHandle;
+
+pub type SurfaceId = SlotHandle;
+
+// A window's surface and its configuration, every surface shares the context's device
+pub struct RenderSurface<'a> {
+    pub surface: Surface<'a>,
+    pub config: SurfaceConfiguration,
+}
+
+impl<'a> RenderSurface<'a> {
+    // Zero sized surfaces (minimized windows) can't be configured
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        surface: Surface<'a>,
+        config: SurfaceConfiguration,
+    ) -> Self {
+        let render_surface = Self { surface, config };
+        if render_surface.visible() {
+            render_surface.configure(device);
+        }
+
+        render_surface
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        self.config.format
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.config.width, self.config.height)
+    }
+
+    pub fn visible(&self) -> bool {
+        self.config.width > 0 && self.config.height > 0
+    }
+
+    pub(crate) fn configure(&self, device: &wgpu::Device) {
+        self.surface.configure(device, &self.config);
+    }
+
+    // Returns whether the surface got configured, display_tex skips frames of zero sized
+    // surfaces until the next resize to a visible size
+    pub(crate) fn resize(&mut self, device: &wgpu::Device, size: (u32, u32)) -> bool {
+        self.config.width = size.0;
+        self.config.height = size.1;
+
+        if !self.visible() {
+            return false;
+        }
+
+        self.configure(device);
+        true
+    }
+
+    pub(crate) fn acquire(
+        &self,
+        device: &wgpu::Device,
+    ) -> Result<wgpu::SurfaceTexture, wgpu::SurfaceError> {
+        match self.surface.get_current_texture() {
+            Ok(output) => Ok(output),
+            // The swapchain doesn't match the surface anymore, recreating it once is enough
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                crate::debug!("Reconfiguring lost or outdated surface");
+                self.configure(device);
+                self.surface.get_current_texture()
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
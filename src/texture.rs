@@ -1,17 +1,149 @@
+use std::collections::HashMap;
+
 use wgpu::Extent3d;
 
+use crate::renderer::RenderError;
+use crate::sampler::{Sampler, SamplerDesc, SamplerHandle, TextureBinding};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TextureKind {
+    #[default]
+    D2,
+    D2Array {
+        layers: u32,
+    },
+    D3 {
+        depth: u32,
+    },
+    // Six square layers in the order +X, -X, +Y, -Y, +Z, -Z
+    Cube,
+    CubeArray {
+        cubes: u32,
+    },
+}
+
+impl TextureKind {
+    pub fn dimension(&self) -> wgpu::TextureDimension {
+        match self {
+            TextureKind::D3 { .. } => wgpu::TextureDimension::D3,
+            _ => wgpu::TextureDimension::D2,
+        }
+    }
+
+    pub fn view_dimension(&self) -> wgpu::TextureViewDimension {
+        match self {
+            TextureKind::D2 => wgpu::TextureViewDimension::D2,
+            TextureKind::D2Array { .. } => wgpu::TextureViewDimension::D2Array,
+            TextureKind::D3 { .. } => wgpu::TextureViewDimension::D3,
+            TextureKind::Cube => wgpu::TextureViewDimension::Cube,
+            TextureKind::CubeArray { .. } => wgpu::TextureViewDimension::CubeArray,
+        }
+    }
+
+    // Depth of 3d textures, amount of layers otherwise
+    pub fn layers(&self) -> u32 {
+        match self {
+            TextureKind::D2 => 1,
+            TextureKind::D2Array { layers } => *layers,
+            TextureKind::D3 { depth } => *depth,
+            TextureKind::Cube => 6,
+            TextureKind::CubeArray { cubes } => cubes * 6,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TextureDesc {
+    pub dimensions: (u32, u32),
+    pub kind: TextureKind,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+    pub sampler_type: wgpu::FilterMode,
+    pub mip_level_count: u32,
+}
+
+// What a pipeline layout needs to know about a bound texture, textures with equal keys can
+// share pipelines
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextureLayoutKey {
+    // (sampler, texture) pair
+    Sampled {
+        sample_type: wgpu::TextureSampleType,
+        view_dimension: wgpu::TextureViewDimension,
+        sampler: wgpu::SamplerBindingType,
+    },
+    // Single storage texture, the format is part of the layout
+    Storage {
+        format: wgpu::TextureFormat,
+        access: wgpu::StorageTextureAccess,
+        view_dimension: wgpu::TextureViewDimension,
+    },
+}
+
 pub struct Texture {
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
     pub texture: wgpu::Texture,
     pub texture_view: wgpu::TextureView,
     pub texture_sampler: wgpu::Sampler,
+    // Only the first mip level of the first layer, render passes can't draw into a view
+    // spanning several. None when texture_view already is exactly that
+    pub base_view: Option<wgpu::TextureView>,
     pub dimensions: (u32, u32),
+    pub kind: TextureKind,
+    pub mip_level_count: u32,
     pub format: wgpu::TextureFormat,
     pub usage: wgpu::TextureUsages,
+    pub sample_type: wgpu::TextureSampleType,
+    // Bindings with samplers from the context's cache, created the first time the texture
+    // is drawn with that sampler
+    pub sampler_bindings: HashMap<SamplerHandle, (wgpu::BindGroupLayout, wgpu::BindGroup)>,
+    // Bindings as a storage texture, created the first time the texture is bound that way
+    pub storage_bindings:
+        HashMap<wgpu::StorageTextureAccess, (wgpu::BindGroupLayout, wgpu::BindGroup)>,
+}
+
+// Amount of levels in a full mip chain down to 1x1
+pub fn full_mip_count(dimensions: (u32, u32)) -> u32 {
+    32 - dimensions.0.max(dimensions.1).max(1).leading_zeros()
+}
+
+// Bytes per row and amount of rows of tightly packed texture data, compressed formats
+// count in rows of blocks. None for formats that can't be written to like Depth24Plus
+pub fn data_layout(format: wgpu::TextureFormat, dimensions: (u32, u32)) -> Option<(u32, u32)> {
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_copy_size(Some(wgpu::TextureAspect::All))?;
+
+    Some((
+        dimensions.0.div_ceil(block_width) * block_size,
+        dimensions.1.div_ceil(block_height),
+    ))
+}
+
+// Adapter specific features when the device has them enabled, the guaranteed ones otherwise
+pub fn format_features(
+    adapter: &wgpu::Adapter,
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+) -> wgpu::TextureFormatFeatures {
+    match device
+        .features()
+        .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+    {
+        true => adapter.get_texture_format_features(format),
+        false => format.guaranteed_format_features(device.features()),
+    }
+}
+
+pub fn data_size(format: wgpu::TextureFormat, dimensions: (u32, u32)) -> Option<usize> {
+    let (bytes_per_row, rows) = data_layout(format, dimensions)?;
+
+    Some(bytes_per_row as usize * rows as usize)
 }
 
 impl Texture {
+    // Only the first mip level gets filled with texture_data
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -20,81 +152,88 @@ impl Texture {
         format: wgpu::TextureFormat,
         usage: wgpu::TextureUsages,
         sampler_type: wgpu::FilterMode,
+        mip_level_count: u32,
     ) -> Self {
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: if format == wgpu::TextureFormat::Depth32Float {
-                            wgpu::TextureSampleType::Depth
-                        } else {
-                            wgpu::TextureSampleType::Float { filterable: true }
-                        },
-                    },
-                    count: None,
+        Self::from_desc(
+            device,
+            queue,
+            texture_data,
+            TextureDesc {
+                dimensions,
+                kind: TextureKind::D2,
+                format,
+                usage,
+                sampler_type,
+                mip_level_count,
+            },
+        )
+    }
+
+    // texture_data holds the first mip level of every layer (or depth slice) back to back
+    pub fn from_desc(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture_data: &[u8],
+        desc: TextureDesc,
+    ) -> Self {
+        let TextureDesc {
+            dimensions,
+            kind,
+            format,
+            usage,
+            sampler_type,
+            mip_level_count,
+        } = desc;
+
+        let sample_type = format
+            .sample_type(None, Some(device.features()))
+            .unwrap_or(wgpu::TextureSampleType::Depth);
+
+        // Formats like R32Float or integer ones can only be read through a non filtering sampler
+        let filterable = matches!(
+            sample_type,
+            wgpu::TextureSampleType::Float { filterable: true } | wgpu::TextureSampleType::Depth
+        );
+        let sampler_type = if filterable {
+            sampler_type
+        } else {
+            wgpu::FilterMode::Nearest
+        };
+
+        let bind_group_layout = Self::create_layout(
+            device,
+            TextureLayoutKey::Sampled {
+                sample_type,
+                view_dimension: kind.view_dimension(),
+                sampler: match filterable {
+                    true => wgpu::SamplerBindingType::Filtering,
+                    false => wgpu::SamplerBindingType::NonFiltering,
                 },
-            ],
-        });
+            },
+        );
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: None,
             size: Extent3d {
                 width: dimensions.0,
                 height: dimensions.1,
-                depth_or_array_layers: 1,
+                depth_or_array_layers: kind.layers(),
             },
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
+            dimension: kind.dimension(),
             format, //: wgpu::textureformat::rgba8unormsrgb,
             usage,  //: wgpu::textureusages::texture_binding | wgpu::textureusages::copy_dst
             view_formats: &[],
         });
 
-        if usage & wgpu::TextureUsages::COPY_DST == wgpu::TextureUsages::COPY_DST {
-            queue.write_texture(
-                wgpu::ImageCopyTexture {
-                    aspect: wgpu::TextureAspect::All,
-                    texture: &texture,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
-                },
-                texture_data,
-                wgpu::ImageDataLayout {
-                    offset: 0,
-                    bytes_per_row: Some(4 * dimensions.0),
-                    rows_per_image: Some(dimensions.1),
-                },
-                wgpu::Extent3d {
-                    width: dimensions.0,
-                    height: dimensions.1,
-                    depth_or_array_layers: 1,
-                },
-            );
-        }
-
         let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(kind.view_dimension()),
             ..Default::default()
         });
-        let texture_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: sampler_type,
-            min_filter: sampler_type,
-            mipmap_filter: sampler_type,
-            ..Default::default()
-        });
+        let base_view = (mip_level_count > 1 || kind != TextureKind::D2)
+            .then(|| Self::create_layer_view(&texture, 0, 0));
+        let texture_sampler =
+            device.create_sampler(&SamplerDesc::filtered(sampler_type).descriptor());
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
@@ -111,62 +250,375 @@ impl Texture {
             ],
         });
 
-        crate::debug!("Created new texture");
-
-        Self {
+        let texture = Self {
             bind_group_layout,
             bind_group,
             texture,
             texture_view,
             texture_sampler,
+            base_view,
             dimensions,
+            kind,
+            mip_level_count,
             format,
             usage,
+            sample_type,
+            sampler_bindings: HashMap::new(),
+            storage_bindings: HashMap::new(),
+        };
+
+        if usage.contains(wgpu::TextureUsages::COPY_DST) && !texture_data.is_empty() {
+            texture.write_mip_level(queue, 0, texture_data);
+        }
+
+        crate::debug!("Created new texture");
+
+        texture
+    }
+
+    // from_desc for user supplied data, shared by the context and the resource loader
+    pub(crate) fn from_data(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format_features: wgpu::TextureFormatFeatures,
+        data: &[u8],
+        desc: TextureDesc,
+    ) -> Result<Self, RenderError> {
+        let TextureDesc {
+            dimensions,
+            kind,
+            format,
+            usage,
+            ..
+        } = desc;
+
+        // Storage binding in particular is only available for a few formats
+        if !device.features().contains(format.required_features())
+            || !format_features.allowed_usages.contains(usage)
+        {
+            return Err(RenderError::UnsupportedFormat(format));
+        }
+
+        let (block_width, block_height) = format.block_dimensions();
+        let cube_faces_square = !matches!(kind, TextureKind::Cube | TextureKind::CubeArray { .. })
+            || dimensions.0 == dimensions.1;
+
+        if dimensions.0 % block_width != 0
+            || dimensions.1 % block_height != 0
+            || !cube_faces_square
+            || kind.layers() == 0
+        {
+            return Err(RenderError::InvalidDimensions { format, dimensions });
         }
+
+        let usage = match data.is_empty() {
+            true => usage,
+            false => {
+                let expected = data_size(format, dimensions)
+                    .ok_or(RenderError::UnsupportedFormat(format))?
+                    * kind.layers() as usize;
+                if data.len() != expected {
+                    return Err(RenderError::InvalidDataSize {
+                        expected,
+                        actual: data.len(),
+                    });
+                }
+
+                usage | wgpu::TextureUsages::COPY_DST
+            }
+        };
+
+        Ok(Self::from_desc(
+            device,
+            queue,
+            data,
+            TextureDesc { usage, ..desc },
+        ))
     }
 
-    pub fn update(&mut self, queue: &wgpu::Queue, data: &[u8]) {
+    fn create_layout(device: &wgpu::Device, key: TextureLayoutKey) -> wgpu::BindGroupLayout {
+        let entries = match key {
+            TextureLayoutKey::Sampled {
+                sample_type,
+                view_dimension,
+                sampler,
+            } => vec![
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler(sampler),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension,
+                        sample_type,
+                    },
+                    count: None,
+                },
+            ],
+            TextureLayoutKey::Storage {
+                format,
+                access,
+                view_dimension,
+            } => {
+                // Vertex shaders can't write to storage textures
+                let visibility = match access {
+                    wgpu::StorageTextureAccess::ReadOnly => {
+                        wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE
+                    }
+                    _ => wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                };
+
+                vec![wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access,
+                        format,
+                        view_dimension,
+                    },
+                    count: None,
+                }]
+            }
+        };
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &entries,
+        })
+    }
+
+    // Whether the texture can be read through a sampler with this binding type
+    pub fn supports_sampler(&self, binding_type: wgpu::SamplerBindingType) -> bool {
+        match binding_type {
+            wgpu::SamplerBindingType::Filtering => matches!(
+                self.sample_type,
+                wgpu::TextureSampleType::Float { filterable: true }
+                    | wgpu::TextureSampleType::Depth
+            ),
+            wgpu::SamplerBindingType::NonFiltering => true,
+            wgpu::SamplerBindingType::Comparison => {
+                self.sample_type == wgpu::TextureSampleType::Depth
+            }
+        }
+    }
+
+    pub fn layout_key(&self, sampler: Option<&Sampler>) -> TextureLayoutKey {
+        let sampler = match sampler {
+            Some(sampler) => sampler.desc.binding_type(),
+            None if self.supports_sampler(wgpu::SamplerBindingType::Filtering) => {
+                wgpu::SamplerBindingType::Filtering
+            }
+            None => wgpu::SamplerBindingType::NonFiltering,
+        };
+
+        TextureLayoutKey::Sampled {
+            sample_type: self.sample_type,
+            view_dimension: self.kind.view_dimension(),
+            sampler,
+        }
+    }
+
+    // Cubes can't be storage bound, their faces are written as array layers instead
+    fn storage_view_dimension(&self) -> wgpu::TextureViewDimension {
+        match self.kind {
+            TextureKind::Cube | TextureKind::CubeArray { .. } => {
+                wgpu::TextureViewDimension::D2Array
+            }
+            kind => kind.view_dimension(),
+        }
+    }
+
+    pub fn storage_layout_key(&self, access: wgpu::StorageTextureAccess) -> TextureLayoutKey {
+        TextureLayoutKey::Storage {
+            format: self.format,
+            access,
+            view_dimension: self.storage_view_dimension(),
+        }
+    }
+
+    // Layout and bind group to draw the texture with, None if the binding for the sampler
+    // or storage access wasnt created yet
+    pub fn binding(
+        &self,
+        binding: &TextureBinding,
+    ) -> Option<(&wgpu::BindGroupLayout, &wgpu::BindGroup)> {
+        let bindings = match (binding.storage, binding.sampler) {
+            (Some(access), _) => self.storage_bindings.get(&access),
+            (None, Some(sampler)) => self.sampler_bindings.get(&sampler),
+            (None, None) => return Some((&self.bind_group_layout, &self.bind_group)),
+        };
+
+        bindings.map(|(layout, bind_group)| (layout, bind_group))
+    }
+
+    pub fn create_sampler_binding_if_doesnt_exist(
+        &mut self,
+        device: &wgpu::Device,
+        handle: SamplerHandle,
+        sampler: &Sampler,
+    ) {
+        if self.sampler_bindings.contains_key(&handle) {
+            return;
+        }
+
+        let bind_group_layout = Self::create_layout(device, self.layout_key(Some(sampler)));
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&sampler.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&self.texture_view),
+                },
+            ],
+        });
+
+        self.sampler_bindings
+            .insert(handle, (bind_group_layout, bind_group));
+        crate::debug!("Created new sampler texture binding");
+    }
+
+    // Data for every layer of the first mip level
+    pub fn update(&mut self, queue: &wgpu::Queue, data: &[u8]) -> Result<(), RenderError> {
+        if !self.usage.contains(wgpu::TextureUsages::COPY_DST) {
+            return Err(RenderError::MissingUsage(wgpu::TextureUsages::COPY_DST));
+        }
+
+        let expected = data_size(self.format, self.mip_level_size(0))
+            .ok_or(RenderError::UnsupportedFormat(self.format))?
+            * self.mip_level_layers(0) as usize;
+        if data.len() != expected {
+            return Err(RenderError::InvalidDataSize {
+                expected,
+                actual: data.len(),
+            });
+        }
+
+        self.write_mip_level(queue, 0, data);
+        Ok(())
+    }
+
+    // Data for every layer of the level back to back
+    pub fn write_mip_level(&self, queue: &wgpu::Queue, level: u32, data: &[u8]) {
+        self.write_layers(queue, level, 0, self.mip_level_layers(level), data);
+    }
+
+    pub fn write_layer(&self, queue: &wgpu::Queue, layer: u32, level: u32, data: &[u8]) {
+        self.write_layers(queue, level, layer, 1, data);
+    }
+
+    fn write_layers(
+        &self,
+        queue: &wgpu::Queue,
+        level: u32,
+        first_layer: u32,
+        layers: u32,
+        data: &[u8],
+    ) {
+        let (width, height) = self.mip_level_size(level);
+        let (bytes_per_row, rows_per_image) =
+            data_layout(self.format, (width, height)).expect("format can't be uploaded to");
+
+        // Compressed levels smaller than a block still get copied as a whole block
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: layers,
+        }
+        .physical_size(self.format);
+
         queue.write_texture(
             wgpu::ImageCopyTexture {
                 aspect: wgpu::TextureAspect::All,
                 texture: &self.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
+                mip_level: level,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: first_layer,
+                },
             },
             data,
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: Some(4 * self.dimensions.0),
-                rows_per_image: Some(self.dimensions.1),
-            },
-            wgpu::Extent3d {
-                width: self.dimensions.0,
-                height: self.dimensions.1,
-                depth_or_array_layers: 1,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(rows_per_image),
             },
+            size,
         );
     }
 
+    pub fn mip_level_size(&self, level: u32) -> (u32, u32) {
+        (
+            (self.dimensions.0 >> level).max(1),
+            (self.dimensions.1 >> level).max(1),
+        )
+    }
+
+    // 3d textures shrink in depth with every level, array layers stay
+    pub fn mip_level_layers(&self, level: u32) -> u32 {
+        match self.kind {
+            TextureKind::D3 { depth } => (depth >> level).max(1),
+            kind => kind.layers(),
+        }
+    }
+
+    // Plain 2d view of a single layer (or depth slice) and mip level
+    pub fn create_layer_view(texture: &wgpu::Texture, level: u32, layer: u32) -> wgpu::TextureView {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_mip_level: level,
+            mip_level_count: Some(1),
+            base_array_layer: layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        })
+    }
+
+    // View to use as a render pass attachment
+    pub fn target_view(&self) -> &wgpu::TextureView {
+        self.base_view.as_ref().unwrap_or(&self.texture_view)
+    }
+
     pub fn destroy(self) {
         self.texture.destroy();
     }
 
+    // Mipmapped textures get a full chain for the new size, the contents are lost either way
     pub fn resize(&mut self, device: &wgpu::Device, dimensions: (u32, u32)) {
+        let mip_level_count = match self.mip_level_count {
+            1 => 1,
+            _ => full_mip_count(dimensions),
+        };
+
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: None,
             size: Extent3d {
                 width: dimensions.0,
                 height: dimensions.1,
-                depth_or_array_layers: 1,
+                depth_or_array_layers: self.kind.layers(),
             },
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
+            dimension: self.kind.dimension(),
             format: self.format, //: wgpu::textureformat::rgba8unormsrgb,
             usage: self.usage, //: wgpu::textureusages::texture_binding | wgpu::textureusages::copy_dst
             view_formats: &[],
         });
-        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(self.kind.view_dimension()),
+            ..Default::default()
+        });
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout: &self.bind_group_layout,
@@ -184,8 +636,44 @@ impl Texture {
 
         // NOTE: idk if texture.destroy() has to be called or if it is called automatically
         self.texture_view = texture_view;
+        self.base_view = (mip_level_count > 1 || self.kind != TextureKind::D2)
+            .then(|| Self::create_layer_view(&texture, 0, 0));
         self.texture = texture;
         self.bind_group = bind_group;
         self.dimensions = dimensions;
+        self.mip_level_count = mip_level_count;
+        self.sampler_bindings.clear();
+        self.storage_bindings.clear();
+    }
+
+    pub fn create_storage_binding_if_doesnt_exist(
+        &mut self,
+        device: &wgpu::Device,
+        access: wgpu::StorageTextureAccess,
+    ) {
+        if self.storage_bindings.contains_key(&access) {
+            return;
+        }
+
+        let bind_group_layout = Self::create_layout(device, self.storage_layout_key(access));
+
+        let view = self.texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(self.storage_view_dimension()),
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            }],
+        });
+
+        self.storage_bindings
+            .insert(access, (bind_group_layout, bind_group));
+        crate::debug!("Created new storage texture binding");
     }
 }
@@ -0,0 +1,40 @@
+// Format render targets get when the context is built with hdr enabled
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TonemapOperator {
+    // Plain clamp to the 0-1 range
+    None,
+    Reinhard,
+    #[default]
+    Aces,
+    AgX,
+}
+
+// How display_tex maps hdr textures to the surface. The operator only applies when
+// presenting to an sdr surface, exposure applies to every hdr texture
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tonemapping {
+    pub operator: TonemapOperator,
+    pub exposure: f32,
+}
+
+impl Default for Tonemapping {
+    fn default() -> Self {
+        Self {
+            operator: TonemapOperator::default(),
+            exposure: 1.0,
+        }
+    }
+}
+
+// Float formats which can hold values above 1
+pub fn is_hdr_format(format: wgpu::TextureFormat) -> bool {
+    matches!(
+        format,
+        wgpu::TextureFormat::Rgba16Float
+            | wgpu::TextureFormat::Rgba32Float
+            | wgpu::TextureFormat::Rg11b10Float
+            | wgpu::TextureFormat::Rgb9e5Ufloat
+    )
+}
@@ -1,6 +1,3 @@
-use encase::UniformBuffer;
-use wgpu::util::DeviceExt;
-
 pub struct UniformHandle {
     pub min_size: u64,
     pub stages: wgpu::ShaderStages,
@@ -12,6 +9,7 @@ pub struct UniformBindGroup {
     pub buffer: wgpu::Buffer,
     pub min_size: u64,
     pub max_size: u64,
+    pub stages: wgpu::ShaderStages,
 }
 
 impl UniformBindGroup {
@@ -62,6 +60,7 @@ impl UniformBindGroup {
             buffer,
             min_size,
             max_size,
+            stages: uniform.stages,
         }
     }
 
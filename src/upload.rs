@@ -0,0 +1,148 @@
+use wgpu::util::DeviceExt;
+
+use crate::renderer::TextureHandle;
+use crate::slotmap::SlotMap;
+use crate::texture::{data_layout, Texture};
+
+struct PendingRegion {
+    texture: TextureHandle,
+    mip_level: u32,
+    origin: (u32, u32),
+    size: wgpu::Extent3d,
+    offset: u64,
+    bytes_per_row: u32,
+    rows: u32,
+}
+
+// Texture region writes collected over a frame. They all share one staging buffer which is
+// copied from in a single submission, instead of every small write allocating its own
+#[derive(Default)]
+pub(crate) struct TextureUploads {
+    data: Vec<u8>,
+    regions: Vec<PendingRegion>,
+}
+
+impl TextureUploads {
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    // Region has to be validated already and not be empty, data is tightly packed rows
+    pub fn push(
+        &mut self,
+        texture_handle: TextureHandle,
+        texture: &Texture,
+        mip_level: u32,
+        origin: (u32, u32),
+        size: (u32, u32),
+        data: &[u8],
+    ) {
+        let (bytes_per_row, rows) = data_layout(texture.format, size).unwrap();
+        let padded_bytes_per_row =
+            wgpu::util::align_to(bytes_per_row, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        // Buffer copies need the rows and the start of every region aligned
+        let offset = wgpu::util::align_to(
+            self.data.len() as u64,
+            wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64,
+        );
+        self.data.resize(offset as usize, 0);
+
+        for row in data.chunks_exact(bytes_per_row as usize) {
+            self.data.extend_from_slice(row);
+            self.data.resize(
+                self.data.len() + (padded_bytes_per_row - bytes_per_row) as usize,
+                0,
+            );
+        }
+
+        // Regions touching the edge of a compressed texture still copy whole blocks
+        let (block_width, block_height) = texture.format.block_dimensions();
+
+        self.regions.push(PendingRegion {
+            texture: texture_handle,
+            mip_level,
+            origin,
+            size: wgpu::Extent3d {
+                width: size.0.div_ceil(block_width) * block_width,
+                height: size.1.div_ceil(block_height) * block_height,
+                depth_or_array_layers: 1,
+            },
+            offset,
+            bytes_per_row: padded_bytes_per_row,
+            rows,
+        });
+    }
+
+    pub fn flush(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        textures: &SlotMap<Texture>,
+    ) {
+        if self.regions.is_empty() {
+            return;
+        }
+
+        let staging = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("texture upload buffer"),
+            contents: &self.data,
+            usage: wgpu::BufferUsages::COPY_SRC,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Texture upload encoder"),
+        });
+
+        for region in self.regions.drain(..) {
+            // Destroyed since the write was issued
+            let Some(texture) = textures.get(region.texture) else {
+                continue;
+            };
+
+            // Or resized and the region doesnt fit anymore
+            let physical = {
+                let (width, height) = texture.mip_level_size(region.mip_level);
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                }
+                .physical_size(texture.format)
+            };
+            if region.mip_level >= texture.mip_level_count
+                || region.origin.0 + region.size.width > physical.width
+                || region.origin.1 + region.size.height > physical.height
+            {
+                continue;
+            }
+
+            encoder.copy_buffer_to_texture(
+                wgpu::ImageCopyBuffer {
+                    buffer: &staging,
+                    layout: wgpu::ImageDataLayout {
+                        offset: region.offset,
+                        bytes_per_row: Some(region.bytes_per_row),
+                        rows_per_image: Some(region.rows),
+                    },
+                },
+                wgpu::ImageCopyTexture {
+                    texture: &texture.texture,
+                    mip_level: region.mip_level,
+                    origin: wgpu::Origin3d {
+                        x: region.origin.0,
+                        y: region.origin.1,
+                        z: 0,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                region.size,
+            );
+        }
+
+        queue.submit(std::iter::once(encoder.finish()));
+        self.data.clear();
+
+        crate::debug!("Flushed texture uploads");
+    }
+}
@@ -1,4 +1,3 @@
-use encase::{internal::WriteInto, ShaderSize, ShaderType};
 use nalgebra_glm as glm;
 use wgpu::VertexFormat;
 